@@ -0,0 +1,185 @@
+use crate::history::now;
+use crate::providers::Conversation;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// A response saved for a request, and which provider gave it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cached {
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub provider: String,
+    pub model: String,
+    pub response: String,
+}
+
+impl Cached {
+    pub fn new(provider: &str, model: &str, response: &str) -> Self {
+        Cached {
+            timestamp: now(),
+            provider: provider.to_string(),
+            model: model.to_string(),
+            response: response.to_string(),
+        }
+    }
+}
+
+/// Responses on disk, one file per request
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    /// The cache in `$XDG_CACHE_HOME/halp/responses`, falling back to
+    /// `~/.cache/halp/responses`
+    pub fn open() -> Option<Self> {
+        let cache_dir = std::env::var("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .ok()
+            .or_else(|| dirs::home_dir().map(|h| h.join(".cache")))?;
+
+        Some(Self::at(cache_dir.join("halp").join("responses")))
+    }
+
+    pub fn at(dir: impl Into<PathBuf>) -> Self {
+        Cache { dir: dir.into() }
+    }
+
+    /// The key for a request: a hash of everything that goes into it, so
+    /// changing the model, the system prompt (which includes the OS, shell
+    /// and working directory) or the query asks the provider again
+    pub fn key(
+        provider: &str,
+        model: &str,
+        system_prompt: &str,
+        conversation: &Conversation,
+    ) -> String {
+        let mut hasher = Sha256::new();
+        let mut field = |value: &str| {
+            // Length-prefixed, so moving text between fields changes the key
+            hasher.update((value.len() as u64).to_le_bytes());
+            hasher.update(value);
+        };
+        field(provider);
+        field(model);
+        field(system_prompt);
+        for turn in &conversation.turns {
+            field(turn.role.as_str());
+            field(&turn.content);
+        }
+        hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", key))
+    }
+
+    /// The response saved under `key`, unless it's older than `ttl`
+    pub fn get(&self, key: &str, ttl: Duration) -> Option<Cached> {
+        let path = self.path(key);
+        let cached: Cached = serde_json::from_str(&fs::read_to_string(&path).ok()?).ok()?;
+        if now().saturating_sub(cached.timestamp) >= ttl.as_secs() {
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+        Some(cached)
+    }
+
+    pub fn put(&self, key: &str, cached: &Cached) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let content = serde_json::to_string(cached).map_err(io::Error::other)?;
+        // Through a temporary file, so a concurrent `get` never reads half a response
+        let temp = self.dir.join(format!("{}.{}.tmp", key, std::process::id()));
+        fs::write(&temp, content)?;
+        fs::rename(&temp, self.path(key))
+    }
+
+    /// Remove every saved response, returning how many there were
+    pub fn clear(&self) -> io::Result<usize> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        let mut removed = 0;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                removed += 1;
+            }
+            fs::remove_file(path)?;
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key() {
+        let conversation = Conversation::new("list files");
+        let key = Cache::key("ollama", "llama3.2", "system", &conversation);
+        assert_eq!(key.len(), 64);
+        assert_eq!(
+            key,
+            Cache::key("ollama", "llama3.2", "system", &conversation)
+        );
+
+        assert_ne!(key, Cache::key("ollama", "qwen3", "system", &conversation));
+        assert_ne!(
+            key,
+            Cache::key("ollama", "llama3.2", "other system", &conversation)
+        );
+        assert_ne!(
+            key,
+            Cache::key(
+                "ollama",
+                "llama3.2",
+                "system",
+                &Conversation::new("list all files")
+            )
+        );
+        // Text moved between fields
+        assert_ne!(
+            Cache::key("ab", "c", "system", &conversation),
+            Cache::key("a", "bc", "system", &conversation)
+        );
+    }
+
+    #[test]
+    fn test_get_put_clear() {
+        let dir = std::env::temp_dir().join(format!("halp-cache-test-{}", std::process::id()));
+        let cache = Cache::at(&dir);
+        let day = Duration::from_secs(86400);
+        assert_eq!(cache.get("key", day), None);
+
+        let cached = Cached::new("ollama", "llama3.2", "COMMAND: ls -la");
+        cache.put("key", &cached).unwrap();
+        assert_eq!(cache.get("key", day), Some(cached.clone()));
+
+        // Expired responses are dropped
+        let stale = Cached {
+            timestamp: cached.timestamp - 2 * 86400,
+            ..cached
+        };
+        cache.put("stale", &stale).unwrap();
+        assert_eq!(cache.get("stale", day), None);
+        assert!(!dir.join("stale.json").exists());
+
+        assert_eq!(cache.clear().unwrap(), 1);
+        assert_eq!(cache.get("key", day), None);
+        assert_eq!(cache.clear().unwrap(), 0);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+}
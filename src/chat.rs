@@ -0,0 +1,145 @@
+use crate::output::{BOLD, RESET};
+use crate::run::open_tty;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+
+/// What the user typed at the chat prompt
+#[derive(Debug, Clone, PartialEq)]
+pub enum Input {
+    /// A new request, or a follow-up refining the last command
+    Query(String),
+    /// Print the current command to stdout and end the session
+    Accept,
+    /// Copy the current command to the clipboard
+    Copy,
+    /// Run the current command
+    Run,
+    /// Forget the conversation so far
+    New,
+    Help,
+    Quit,
+    /// A `/` command we don't know
+    Unknown(String),
+    Empty,
+}
+
+pub const HELP: &str = "\
+Type a request, then follow-ups to refine the command (\"use fd instead\").
+  /accept, /a  print the command to stdout and exit
+  /copy, /c    copy the command to the clipboard
+  /run, /r     run the command
+  /new         start over without the previous exchange
+  /quit, /q    exit without printing anything (or Ctrl-D)";
+
+pub fn parse_input(line: &str) -> Input {
+    let line = line.trim();
+    if line.is_empty() {
+        return Input::Empty;
+    }
+    let Some(command) = line.strip_prefix('/') else {
+        return Input::Query(line.to_string());
+    };
+    match command {
+        "accept" | "a" => Input::Accept,
+        "copy" | "c" => Input::Copy,
+        "run" | "r" => Input::Run,
+        "new" => Input::New,
+        "help" | "h" | "?" => Input::Help,
+        "quit" | "q" | "exit" => Input::Quit,
+        // An absolute path is a query, e.g. "/var/log is full, clean it up"
+        _ if command.contains('/') || command.contains(' ') => Input::Query(line.to_string()),
+        _ => Input::Unknown(line.to_string()),
+    }
+}
+
+/// The command as shown in a session
+pub fn highlight(command: &str) -> String {
+    format!("{}{}{}", BOLD, command, RESET)
+}
+
+/// Reads lines from the terminal, which is still there when stdin is piped
+pub struct LineReader {
+    tty: File,
+    reader: BufReader<File>,
+}
+
+impl LineReader {
+    pub fn open() -> io::Result<Self> {
+        let tty = open_tty()?;
+        let reader = BufReader::new(tty.try_clone()?);
+        Ok(Self { tty, reader })
+    }
+
+    /// Show the prompt and read a line, or `None` at EOF (Ctrl-D)
+    pub fn read_line(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        write!(self.tty, "{}", prompt)?;
+        self.tty.flush()?;
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            writeln!(self.tty)?;
+            return Ok(None);
+        }
+        Ok(Some(line))
+    }
+}
+
+/// Clipboard tools to try, in order, with their arguments
+const CLIPBOARD_TOOLS: &[&[&str]] = &[
+    &["pbcopy"],
+    &["wl-copy"],
+    &["xclip", "-selection", "clipboard"],
+    &["xsel", "--clipboard", "--input"],
+    &["clip.exe"],
+];
+
+/// Copy text with the first clipboard tool that's installed
+pub fn copy_to_clipboard(text: &str) -> io::Result<()> {
+    for tool in CLIPBOARD_TOOLS {
+        let child = Command::new(tool[0])
+            .args(&tool[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+        if child.wait()?.success() {
+            return Ok(());
+        }
+    }
+    Err(io::Error::other(
+        "no clipboard tool found (pbcopy, wl-copy, xclip or xsel)",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_input() {
+        assert_eq!(
+            parse_input("use fd instead\n"),
+            Input::Query("use fd instead".to_string())
+        );
+        assert_eq!(parse_input("/accept\n"), Input::Accept);
+        assert_eq!(parse_input(" /r "), Input::Run);
+        assert_eq!(parse_input("/q"), Input::Quit);
+        assert_eq!(parse_input("\n"), Input::Empty);
+        assert_eq!(
+            parse_input("/frobnicate"),
+            Input::Unknown("/frobnicate".to_string())
+        );
+        assert_eq!(
+            parse_input("/var/log is full"),
+            Input::Query("/var/log is full".to_string())
+        );
+    }
+}
@@ -1,7 +1,19 @@
+use crate::error::HalpError;
 use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::env;
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const DEFAULT_AZURE_API_VERSION: &str = "2024-10-21";
+
+/// Default cap on piped stdin context (64KB)
+const DEFAULT_MAX_STDIN_BYTES: usize = 65_536;
+/// How long a cached response is reused by default (a day)
+const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
 
 #[derive(Debug, Clone, PartialEq, Default)]
 pub enum Provider {
@@ -9,6 +21,68 @@ pub enum Provider {
     Anthropic,
     OpenAI,
     Gemini,
+    Ollama,
+    OpenAICompatible,
+    Azure,
+}
+
+/// Which request field carries the output token limit
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MaxTokensField {
+    #[default]
+    MaxTokens,
+    MaxCompletionTokens,
+}
+
+/// Request options for OpenAI-style chat completion endpoints
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenAIOptions {
+    /// Header that carries the API key
+    pub auth_header: String,
+    /// Prefix placed before the API key in the auth header (empty for the bare key)
+    pub auth_scheme: String,
+    pub headers: BTreeMap<String, String>,
+    pub query_params: BTreeMap<String, String>,
+    pub max_tokens_field: MaxTokensField,
+}
+
+impl Default for OpenAIOptions {
+    fn default() -> Self {
+        Self {
+            auth_header: "Authorization".to_string(),
+            auth_scheme: "Bearer".to_string(),
+            headers: BTreeMap::new(),
+            query_params: BTreeMap::new(),
+            max_tokens_field: MaxTokensField::default(),
+        }
+    }
+}
+
+/// Deployment settings for Azure OpenAI
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct AzureOptions {
+    /// Resource endpoint, e.g. https://my-resource.openai.azure.com
+    pub resource: String,
+    pub deployment: String,
+    pub api_version: String,
+}
+
+/// Retry behavior for transient failures (429, 503, 529, connection errors)
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryOptions {
+    pub max_retries: u32,
+    /// Total time allowed for all attempts and waits
+    pub budget: Duration,
+}
+
+impl Default for RetryOptions {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            budget: Duration::from_secs(20),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -18,87 +92,408 @@ pub struct Config {
     pub api_key: String,
     pub api_base_url: Option<String>,
     pub system_prompt: Option<String>,
+    pub openai: OpenAIOptions,
+    pub azure: AzureOptions,
+    pub retry: RetryOptions,
+    /// Providers to try, in order, when this one fails
+    pub fallbacks: Vec<Config>,
+    /// Query the primary and fallbacks at once and take the fastest answer
+    pub race: bool,
+    /// Most bytes of piped stdin to include as context
+    pub max_stdin_bytes: usize,
+    /// Exit with an error instead of success when the command is high risk
+    pub fail_on_high_risk: bool,
+    /// Record generated commands in the history file
+    pub history: bool,
+    /// How long to reuse a cached response; zero turns the cache off
+    pub cache_ttl: Duration,
+    /// Problems with the configuration that didn't stop it loading, e.g. a
+    /// fallback provider that was left out of the chain
+    pub warnings: Vec<String>,
 }
 
 #[derive(Debug, Deserialize, Default)]
 struct FileConfig {
+    #[serde(flatten)]
+    settings: ProviderSettings,
+    system_prompt: Option<String>,
+    providers: Option<Vec<ProviderEntry>>,
+    #[serde(default)]
+    race: bool,
+    max_retries: Option<u32>,
+    retry_budget_secs: Option<u64>,
+    max_stdin_bytes: Option<usize>,
+    #[serde(default)]
+    fail_on_high_risk: bool,
+    history: Option<bool>,
+    cache_ttl_secs: Option<u64>,
+}
+
+/// Settings for a single provider, shared by the top level and `providers` entries
+#[derive(Debug, Deserialize, Default, Clone)]
+struct ProviderSettings {
     provider: Option<String>,
     model: Option<String>,
     api_key: Option<String>,
     api_base_url: Option<String>,
-    system_prompt: Option<String>,
+    auth_header: Option<String>,
+    auth_scheme: Option<String>,
+    headers: Option<BTreeMap<String, String>>,
+    query_params: Option<BTreeMap<String, String>>,
+    max_tokens_field: Option<MaxTokensField>,
+    azure_resource: Option<String>,
+    azure_deployment: Option<String>,
+    azure_api_version: Option<String>,
+}
+
+/// An entry in the `providers` chain: either a bare provider name or a table
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ProviderEntry {
+    Name(String),
+    Settings(Box<ProviderSettings>),
+}
+
+impl ProviderEntry {
+    fn into_settings(self) -> ProviderSettings {
+        match self {
+            ProviderEntry::Name(provider) => ProviderSettings {
+                provider: Some(provider),
+                ..Default::default()
+            },
+            ProviderEntry::Settings(settings) => *settings,
+        }
+    }
+}
+
+impl ProviderSettings {
+    /// Fill any unset fields from `defaults`. The other settings belong to
+    /// the provider they were written for, so for a different provider only
+    /// the provider is filled in: its model, key and endpoint would be wrong,
+    /// and the key must not be sent to someone else.
+    fn with_defaults(self, defaults: &ProviderSettings) -> ProviderSettings {
+        let same_provider = match (&self.provider, &defaults.provider) {
+            (Some(name), Some(default)) => {
+                parse_provider(name).ok() == parse_provider(default).ok()
+            }
+            (Some(name), None) => parse_provider(name).ok() == Some(Provider::default()),
+            (None, _) => true,
+        };
+        if !same_provider {
+            return self;
+        }
+
+        let defaults = defaults.clone();
+        ProviderSettings {
+            provider: self.provider.or(defaults.provider),
+            model: self.model.or(defaults.model),
+            api_key: self.api_key.or(defaults.api_key),
+            api_base_url: self.api_base_url.or(defaults.api_base_url),
+            auth_header: self.auth_header.or(defaults.auth_header),
+            auth_scheme: self.auth_scheme.or(defaults.auth_scheme),
+            headers: self.headers.or(defaults.headers),
+            query_params: self.query_params.or(defaults.query_params),
+            max_tokens_field: self.max_tokens_field.or(defaults.max_tokens_field),
+            azure_resource: self.azure_resource.or(defaults.azure_resource),
+            azure_deployment: self.azure_deployment.or(defaults.azure_deployment),
+            azure_api_version: self.azure_api_version.or(defaults.azure_api_version),
+        }
+    }
+}
+
+/// The provider for a name in the config file or `HALP_PROVIDER`
+fn parse_provider(name: &str) -> Result<Provider, HalpError> {
+    match name.to_lowercase().as_str() {
+        "anthropic" | "claude" => Ok(Provider::Anthropic),
+        "openai" | "gpt" => Ok(Provider::OpenAI),
+        "gemini" | "google" => Ok(Provider::Gemini),
+        "ollama" => Ok(Provider::Ollama),
+        "openai_compatible" | "openai-compatible" => Ok(Provider::OpenAICompatible),
+        "azure" | "azure_openai" => Ok(Provider::Azure),
+        other => Err(HalpError::Config(format!(
+            "Unknown provider '{}'. Use 'anthropic', 'openai', 'gemini', 'ollama', 'openai_compatible', or 'azure'.",
+            other
+        ))),
+    }
+}
+
+impl Provider {
+    /// The model used when none is configured, if the provider has one
+    pub fn default_model(&self) -> Option<&'static str> {
+        match self {
+            Provider::Anthropic => Some("claude-haiku-4-5"),
+            Provider::OpenAI => Some("gpt-5-nano"),
+            Provider::Gemini => Some("gemini-2.5-flash"),
+            Provider::Ollama => Some("llama3.2"),
+            Provider::OpenAICompatible | Provider::Azure => None,
+        }
+    }
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Provider::Anthropic => "anthropic",
+            Provider::OpenAI => "openai",
+            Provider::Gemini => "gemini",
+            Provider::Ollama => "ollama",
+            Provider::OpenAICompatible => "openai_compatible",
+            Provider::Azure => "azure",
+        };
+        write!(f, "{}", name)
+    }
 }
 
 impl Config {
-    pub fn load() -> Result<Self, String> {
-        let file_config = Self::load_file_config();
+    /// Settings for `provider` with its default model and no API key, for
+    /// building a configuration in code rather than loading it
+    pub fn new(provider: Provider) -> Self {
+        Config {
+            model: provider.default_model().unwrap_or_default().to_string(),
+            provider,
+            api_key: String::new(),
+            api_base_url: None,
+            system_prompt: None,
+            openai: OpenAIOptions::default(),
+            azure: AzureOptions::default(),
+            retry: RetryOptions::default(),
+            fallbacks: Vec::new(),
+            race: false,
+            max_stdin_bytes: DEFAULT_MAX_STDIN_BYTES,
+            fail_on_high_risk: false,
+            history: true,
+            cache_ttl: DEFAULT_CACHE_TTL,
+            warnings: Vec::new(),
+        }
+    }
 
-        let provider = Self::resolve_provider(&file_config)?;
-        let model = Self::resolve_model(&provider, &file_config);
-        let api_key = Self::resolve_api_key(&provider, &file_config)?;
-        let api_base_url = Self::resolve_api_base_url(&file_config);
+    /// Load from `~/.config/halp/config.toml` and the environment
+    pub fn load() -> Result<Self, HalpError> {
+        Self::from_file_config(Self::load_file_config()?, &|name| env::var(name).ok())
+    }
+
+    /// Resolve the file's settings, with `env` looking up environment variables
+    fn from_file_config(
+        file_config: FileConfig,
+        env: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<Self, HalpError> {
+        // Without a `providers` chain the top-level settings describe the only provider.
+        // With one, its first entry is the primary and inherits the top-level settings;
+        // later entries stand on their own.
+        let settings = match file_config.providers {
+            Some(entries) if !entries.is_empty() => entries
+                .into_iter()
+                .enumerate()
+                .map(|(i, entry)| {
+                    let settings = entry.into_settings();
+                    if i == 0 {
+                        settings.with_defaults(&file_config.settings)
+                    } else {
+                        settings
+                    }
+                })
+                .collect(),
+            _ => vec![file_config.settings],
+        };
+
+        // A fallback that can't be used is left out rather than stopping halp,
+        // since the providers before it may well work
+        let mut chain = Vec::new();
+        let mut warnings = Vec::new();
+        for (i, settings) in settings.iter().enumerate() {
+            match Self::resolve(settings, i == 0, file_config.system_prompt.clone(), env) {
+                Ok(config) => chain.push(config),
+                Err(e) if i > 0 => warnings.push(format!(
+                    "Skipping fallback provider {} ({}): {}",
+                    i + 1,
+                    settings.provider.as_deref().unwrap_or("anthropic"),
+                    e
+                )),
+                Err(e) => return Err(e),
+            }
+        }
+
+        let defaults = RetryOptions::default();
+        let retry = RetryOptions {
+            max_retries: file_config.max_retries.unwrap_or(defaults.max_retries),
+            budget: file_config
+                .retry_budget_secs
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.budget),
+        };
+        for config in &mut chain {
+            config.retry = retry.clone();
+        }
+
+        let mut primary = chain.remove(0);
+        primary.fallbacks = chain;
+        primary.warnings = warnings;
+        primary.race = file_config.race;
+        primary.fail_on_high_risk = file_config.fail_on_high_risk;
+        primary.history = file_config.history.unwrap_or(true);
+        if let Some(secs) = file_config.cache_ttl_secs {
+            primary.cache_ttl = Duration::from_secs(secs);
+        }
+        if let Some(max_stdin_bytes) = file_config.max_stdin_bytes {
+            primary.max_stdin_bytes = max_stdin_bytes;
+        }
+        Ok(primary)
+    }
+
+    /// A short label for messages, e.g. "openai (gpt-5-nano)"
+    pub fn label(&self) -> String {
+        format!("{} ({})", self.provider, self.model)
+    }
+
+    /// Resolve one provider's settings. `HALP_*` environment variables only
+    /// override the primary provider.
+    fn resolve(
+        settings: &ProviderSettings,
+        primary: bool,
+        system_prompt: Option<String>,
+        env: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<Config, HalpError> {
+        let provider = Self::resolve_provider(settings, primary, env)?;
+        let model = Self::resolve_model(&provider, settings, primary, env)?;
+        let api_key = Self::resolve_api_key(&provider, settings, primary, env)?;
+        let api_base_url = Self::resolve_api_base_url(settings, primary, env);
+        let openai = Self::resolve_openai_options(settings);
+        let azure = Self::resolve_azure_options(&provider, &model, settings, env)?;
+
+        if provider == Provider::OpenAICompatible && api_base_url.is_none() {
+            return Err(HalpError::Config(
+                "The openai_compatible provider requires api_base_url (or HALP_API_BASE_URL)"
+                    .to_string(),
+            ));
+        }
 
         Ok(Config {
             provider,
             model,
             api_key,
             api_base_url,
-            system_prompt: file_config.system_prompt,
+            system_prompt,
+            openai,
+            azure,
+            retry: RetryOptions::default(),
+            fallbacks: Vec::new(),
+            race: false,
+            max_stdin_bytes: DEFAULT_MAX_STDIN_BYTES,
+            fail_on_high_risk: false,
+            history: true,
+            cache_ttl: DEFAULT_CACHE_TTL,
+            warnings: Vec::new(),
         })
     }
 
-    fn config_path() -> Option<PathBuf> {
+    /// The directory holding `config.toml` and `policy.toml`
+    pub(crate) fn config_dir() -> Option<PathBuf> {
         // Check XDG_CONFIG_HOME first, then fall back to ~/.config
         let config_dir = env::var("XDG_CONFIG_HOME")
             .map(PathBuf::from)
             .ok()
             .or_else(|| dirs::home_dir().map(|h| h.join(".config")))?;
 
-        Some(config_dir.join("halp").join("config.toml"))
+        Some(config_dir.join("halp"))
     }
 
-    fn load_file_config() -> FileConfig {
-        Self::config_path()
-            .and_then(|path| fs::read_to_string(path).ok())
-            .and_then(|content| toml::from_str(&content).ok())
-            .unwrap_or_default()
+    fn config_path() -> Option<PathBuf> {
+        Some(Self::config_dir()?.join("config.toml"))
     }
 
-    fn resolve_provider(file_config: &FileConfig) -> Result<Provider, String> {
-        let provider_str = env::var("HALP_PROVIDER")
-            .ok()
-            .or_else(|| file_config.provider.as_ref().cloned())
-            .unwrap_or_else(|| "anthropic".to_string());
+    fn load_file_config() -> Result<FileConfig, HalpError> {
+        match Self::config_path() {
+            Some(path) => Self::read_file_config(&path),
+            None => Ok(FileConfig::default()),
+        }
+    }
 
-        match provider_str.to_lowercase().as_str() {
-            "anthropic" | "claude" => Ok(Provider::Anthropic),
-            "openai" | "gpt" => Ok(Provider::OpenAI),
-            "gemini" | "google" => Ok(Provider::Gemini),
-            other => Err(format!(
-                "Unknown provider '{}'. Use 'anthropic', 'openai', or 'gemini'.",
-                other
-            )),
+    /// A missing file means the defaults; an unreadable or invalid one is an
+    /// error, so a typo never silently falls back to them
+    fn read_file_config(path: &Path) -> Result<FileConfig, HalpError> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(FileConfig::default()),
+            Err(e) => {
+                return Err(HalpError::Config(format!(
+                    "Failed to read {}: {}",
+                    path.display(),
+                    e
+                )))
+            }
+        };
+        toml::from_str(&content)
+            .map_err(|e| HalpError::Config(format!("{}: Invalid config: {}", path.display(), e)))
+    }
+
+    /// Read a `HALP_*` override, which only applies to the primary provider
+    fn env_override(
+        name: &str,
+        primary: bool,
+        env: &dyn Fn(&str) -> Option<String>,
+    ) -> Option<String> {
+        if primary {
+            env(name)
+        } else {
+            None
         }
     }
 
-    fn resolve_model(provider: &Provider, file_config: &FileConfig) -> String {
-        env::var("HALP_MODEL")
-            .ok()
-            .or_else(|| file_config.model.as_ref().cloned())
-            .unwrap_or_else(|| match provider {
-                Provider::Anthropic => "claude-haiku-4-5".to_string(),
-                Provider::OpenAI => "gpt-5-nano".to_string(),
-                Provider::Gemini => "gemini-2.5-flash".to_string(),
-            })
+    fn resolve_provider(
+        settings: &ProviderSettings,
+        primary: bool,
+        env: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<Provider, HalpError> {
+        let provider_str = Self::env_override("HALP_PROVIDER", primary, env)
+            .or_else(|| settings.provider.as_ref().cloned())
+            .unwrap_or_else(|| "anthropic".to_string());
+
+        parse_provider(&provider_str)
     }
 
-    fn resolve_api_key(provider: &Provider, file_config: &FileConfig) -> Result<String, String> {
+    fn resolve_model(
+        provider: &Provider,
+        settings: &ProviderSettings,
+        primary: bool,
+        env: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<String, HalpError> {
+        if let Some(model) = Self::env_override("HALP_MODEL", primary, env)
+            .or_else(|| settings.model.as_ref().cloned())
+        {
+            return Ok(model);
+        }
+
+        if let Some(model) = provider.default_model() {
+            return Ok(model.to_string());
+        }
+
+        match provider {
+            // Azure routes by deployment, so the deployment name stands in for the model
+            Provider::Azure => settings.azure_deployment.clone().ok_or_else(|| {
+                HalpError::Config(
+                    "The azure provider requires azure_deployment in ~/.config/halp/config.toml"
+                        .to_string(),
+                )
+            }),
+            _ => Err(HalpError::Config(format!(
+                "The {} provider requires a model. Set HALP_MODEL or add model to ~/.config/halp/config.toml",
+                provider
+            ))),
+        }
+    }
+
+    fn resolve_api_key(
+        provider: &Provider,
+        settings: &ProviderSettings,
+        primary: bool,
+        env: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<String, HalpError> {
         // Priority: HALP_API_KEY > config file > provider-specific env var
-        if let Ok(key) = env::var("HALP_API_KEY") {
+        if let Some(key) = Self::env_override("HALP_API_KEY", primary, env) {
             return Ok(key);
         }
 
-        if let Some(key) = file_config.api_key.as_ref() {
+        if let Some(key) = settings.api_key.as_ref() {
             return Ok(key.clone());
         }
 
@@ -106,21 +501,338 @@ impl Config {
             Provider::Anthropic => "ANTHROPIC_API_KEY",
             Provider::OpenAI => "OPENAI_API_KEY",
             Provider::Gemini => "GEMINI_API_KEY",
+            Provider::Azure => "AZURE_OPENAI_API_KEY",
+            // Local models don't need a key, and self-hosted gateways must not
+            // receive a key meant for OpenAI
+            Provider::Ollama | Provider::OpenAICompatible => return Ok(String::new()),
         };
 
-        if let Ok(key) = env::var(provider_env) {
+        if let Some(key) = env(provider_env) {
             return Ok(key);
         }
 
-        Err(format!(
+        Err(HalpError::Auth(format!(
             "No API key found. Set HALP_API_KEY, add api_key to ~/.config/halp/config.toml, or set {}",
             provider_env
-        ))
+        )))
     }
 
-    fn resolve_api_base_url(file_config: &FileConfig) -> Option<String> {
-        env::var("HALP_API_BASE_URL")
-            .ok()
-            .or_else(|| file_config.api_base_url.as_ref().cloned())
+    fn resolve_api_base_url(
+        settings: &ProviderSettings,
+        primary: bool,
+        env: &dyn Fn(&str) -> Option<String>,
+    ) -> Option<String> {
+        Self::env_override("HALP_API_BASE_URL", primary, env)
+            .or_else(|| settings.api_base_url.as_ref().cloned())
+    }
+
+    fn resolve_openai_options(settings: &ProviderSettings) -> OpenAIOptions {
+        let defaults = OpenAIOptions::default();
+        OpenAIOptions {
+            auth_header: settings.auth_header.clone().unwrap_or(defaults.auth_header),
+            auth_scheme: settings.auth_scheme.clone().unwrap_or(defaults.auth_scheme),
+            headers: settings.headers.clone().unwrap_or_default(),
+            query_params: settings.query_params.clone().unwrap_or_default(),
+            max_tokens_field: settings
+                .max_tokens_field
+                .unwrap_or(defaults.max_tokens_field),
+        }
+    }
+
+    fn resolve_azure_options(
+        provider: &Provider,
+        model: &str,
+        settings: &ProviderSettings,
+        env: &dyn Fn(&str) -> Option<String>,
+    ) -> Result<AzureOptions, HalpError> {
+        if *provider != Provider::Azure {
+            return Ok(AzureOptions::default());
+        }
+
+        let resource = settings
+            .azure_resource
+            .clone()
+            .or_else(|| env("AZURE_OPENAI_ENDPOINT"))
+            .ok_or_else(|| {
+                HalpError::Config(
+                    "The azure provider requires azure_resource in ~/.config/halp/config.toml or AZURE_OPENAI_ENDPOINT"
+                        .to_string(),
+                )
+            })?;
+
+        // Accept either a bare resource name or a full endpoint URL
+        let resource = if resource.contains("://") {
+            resource.trim_end_matches('/').to_string()
+        } else {
+            format!("https://{}.openai.azure.com", resource)
+        };
+
+        Ok(AzureOptions {
+            resource,
+            deployment: settings
+                .azure_deployment
+                .clone()
+                .unwrap_or_else(|| model.to_string()),
+            api_version: settings
+                .azure_api_version
+                .clone()
+                .unwrap_or_else(|| DEFAULT_AZURE_API_VERSION.to_string()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Load a config file with no environment variables set
+    fn load(content: &str) -> Result<Config, HalpError> {
+        load_with_env(content, &[])
+    }
+
+    fn load_with_env(content: &str, vars: &[(&str, &str)]) -> Result<Config, HalpError> {
+        Config::from_file_config(toml::from_str(content).unwrap(), &|name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+
+    #[test]
+    fn test_top_level_settings() {
+        let config = load(
+            r#"
+provider = "openai"
+model = "gpt-5-mini"
+api_key = "sk-openai"
+race = true
+max_retries = 5
+cache_ttl_secs = 0
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.provider, Provider::OpenAI);
+        assert_eq!(config.model, "gpt-5-mini");
+        assert_eq!(config.api_key, "sk-openai");
+        assert!(config.fallbacks.is_empty());
+        assert!(config.race);
+        assert_eq!(config.retry.max_retries, 5);
+        assert_eq!(config.cache_ttl, Duration::ZERO);
+        assert!(config.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_chain() {
+        let config = load(
+            r#"
+api_key = "sk-ant"
+max_retries = 1
+providers = [
+    "anthropic",
+    { provider = "ollama", model = "qwen3" },
+    { provider = "openai", api_key = "sk-openai" },
+]
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.label(), "anthropic (claude-haiku-4-5)");
+        assert_eq!(config.api_key, "sk-ant");
+
+        let labels: Vec<_> = config.fallbacks.iter().map(Config::label).collect();
+        assert_eq!(labels, vec!["ollama (qwen3)", "openai (gpt-5-nano)"]);
+        assert_eq!(config.fallbacks[0].api_key, "");
+        assert_eq!(config.fallbacks[1].api_key, "sk-openai");
+        // Retry settings apply to the whole chain
+        assert!(config.fallbacks.iter().all(|c| c.retry.max_retries == 1));
+    }
+
+    #[test]
+    fn test_chain_primary_only_inherits_settings_for_its_provider() {
+        let config = load(
+            r#"
+provider = "claude"
+model = "claude-sonnet-4-5"
+api_key = "sk-ant"
+providers = [{ provider = "ollama" }]
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.provider, Provider::Ollama);
+        assert_eq!(config.model, "llama3.2");
+        assert_eq!(config.api_key, "");
+
+        let config = load(
+            r#"
+provider = "claude"
+model = "claude-sonnet-4-5"
+api_key = "sk-ant"
+providers = [{ provider = "anthropic" }]
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.model, "claude-sonnet-4-5");
+        assert_eq!(config.api_key, "sk-ant");
+    }
+
+    #[test]
+    fn test_unusable_fallback_is_skipped() {
+        let config = load(
+            r#"
+providers = [
+    { provider = "ollama" },
+    { provider = "openai_compatible", model = "llama" },
+    "frobnicate",
+    { provider = "ollama", model = "qwen3" },
+]
+"#,
+        )
+        .unwrap();
+        let labels: Vec<_> = config.fallbacks.iter().map(Config::label).collect();
+        assert_eq!(labels, vec!["ollama (qwen3)"]);
+        assert_eq!(config.warnings.len(), 2);
+        assert!(
+            config.warnings[0].starts_with("Skipping fallback provider 2 (openai_compatible): ")
+        );
+        assert!(config.warnings[1].contains("Unknown provider 'frobnicate'"));
+
+        // The primary still has to work
+        assert!(load(r#"providers = ["frobnicate", "ollama"]"#).is_err());
+    }
+
+    #[test]
+    fn test_environment_overrides_primary_only() {
+        let config = load_with_env(
+            r#"
+providers = ["anthropic", { provider = "openai" }]
+"#,
+            &[
+                ("HALP_MODEL", "claude-sonnet-4-5"),
+                ("HALP_API_KEY", "sk-halp"),
+                ("OPENAI_API_KEY", "sk-openai"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.model, "claude-sonnet-4-5");
+        assert_eq!(config.api_key, "sk-halp");
+        assert_eq!(config.fallbacks[0].model, "gpt-5-nano");
+        assert_eq!(config.fallbacks[0].api_key, "sk-openai");
+
+        let error = load(r#"provider = "anthropic""#).unwrap_err();
+        assert!(matches!(error, HalpError::Auth(_)));
+
+        let config = load_with_env(
+            r#"
+provider = "azure"
+api_key = "azure-key"
+azure_deployment = "gpt-4o-prod"
+"#,
+            &[("AZURE_OPENAI_ENDPOINT", "https://east.openai.azure.com/")],
+        )
+        .unwrap();
+        assert_eq!(config.azure.resource, "https://east.openai.azure.com");
+    }
+
+    #[test]
+    fn test_read_file_config() {
+        let dir = std::env::temp_dir().join(format!("halp-config-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let missing = Config::read_file_config(&dir.join("missing.toml")).unwrap();
+        assert!(missing.providers.is_none());
+
+        let path = dir.join("config.toml");
+        fs::write(&path, "providers = [\"ollama\",\n").unwrap();
+        let error = Config::read_file_config(&path).unwrap_err();
+        assert!(matches!(error, HalpError::Config(_)));
+        assert!(error.to_string().contains(&path.display().to_string()));
+
+        // A directory can't be read as a file
+        assert!(matches!(
+            Config::read_file_config(&dir),
+            Err(HalpError::Config(_))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_openai_compatible_options() {
+        let config = load(
+            r#"
+provider = "openai_compatible"
+model = "llama-3.1-8b"
+api_base_url = "http://localhost:8000/v1/chat/completions"
+api_key = "secret"
+auth_header = "api-key"
+auth_scheme = ""
+headers = { "X-Team" = "tools" }
+query_params = { "tenant" = "dev" }
+max_tokens_field = "max_completion_tokens"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.provider, Provider::OpenAICompatible);
+        assert_eq!(config.openai.auth_header, "api-key");
+        assert_eq!(config.openai.auth_scheme, "");
+        assert_eq!(config.openai.headers["X-Team"], "tools");
+        assert_eq!(config.openai.query_params["tenant"], "dev");
+        assert_eq!(
+            config.openai.max_tokens_field,
+            MaxTokensField::MaxCompletionTokens
+        );
+
+        let error = load(
+            r#"
+provider = "openai_compatible"
+model = "llama-3.1-8b"
+"#,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("requires api_base_url"));
+    }
+
+    #[test]
+    fn test_azure_options() {
+        let config = load(
+            r#"
+provider = "azure"
+api_key = "azure-key"
+azure_resource = "my-resource"
+azure_deployment = "gpt-4o-prod"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.model, "gpt-4o-prod");
+        assert_eq!(
+            config.azure,
+            AzureOptions {
+                resource: "https://my-resource.openai.azure.com".to_string(),
+                deployment: "gpt-4o-prod".to_string(),
+                api_version: DEFAULT_AZURE_API_VERSION.to_string(),
+            }
+        );
+
+        let config = load(
+            r#"
+provider = "azure"
+api_key = "azure-key"
+model = "gpt-4o"
+azure_resource = "https://gateway.example.com/"
+azure_api_version = "2025-01-01-preview"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.azure.resource, "https://gateway.example.com");
+        assert_eq!(config.azure.deployment, "gpt-4o");
+        assert_eq!(config.azure.api_version, "2025-01-01-preview");
+
+        let error = load(
+            r#"
+provider = "azure"
+api_key = "azure-key"
+azure_deployment = "gpt-4o-prod"
+"#,
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("requires azure_resource"));
     }
 }
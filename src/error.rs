@@ -0,0 +1,183 @@
+use reqwest::StatusCode;
+use std::fmt;
+
+/// Everything that can go wrong between reading the config and receiving a
+/// complete response. Each variant has its own process exit code so shell
+/// wrappers can react to specific failures:
+///
+/// | Code | Variant     |
+/// | ---- | ----------- |
+/// | 3    | `Config`    |
+/// | 4    | `Auth`      |
+/// | 5    | `RateLimit` |
+/// | 6    | `Network`   |
+/// | 7    | `Timeout`   |
+/// | 8    | `Api`       |
+/// | 9    | `Parse`     |
+/// | 10   | `SizeLimit` |
+#[derive(Debug, Clone, PartialEq)]
+pub enum HalpError {
+    /// Missing or invalid configuration
+    Config(String),
+    /// No API key is configured, or the provider rejected it (401/403)
+    Auth(String),
+    /// The provider is rate limiting us (429)
+    RateLimit(String),
+    /// The provider could not be reached or the connection dropped
+    Network(String),
+    /// The request took longer than the client timeout
+    Timeout(String),
+    /// Any other error status or error event from the provider
+    Api {
+        status: Option<u16>,
+        message: String,
+    },
+    /// The provider's response could not be parsed
+    Parse(String),
+    /// The response exceeded the size limit (in bytes)
+    SizeLimit(usize),
+}
+
+impl HalpError {
+    /// Classify a non-success HTTP response
+    pub fn from_status(status: StatusCode, body: String) -> Self {
+        match status.as_u16() {
+            401 | 403 => HalpError::Auth(format!("Authentication failed ({}): {}", status, body)),
+            429 => HalpError::RateLimit(format!("Rate limited ({}): {}", status, body)),
+            code => HalpError::Api {
+                status: Some(code),
+                message: body,
+            },
+        }
+    }
+
+    /// Process exit code for this error
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            HalpError::Config(_) => 3,
+            HalpError::Auth(_) => 4,
+            HalpError::RateLimit(_) => 5,
+            HalpError::Network(_) => 6,
+            HalpError::Timeout(_) => 7,
+            HalpError::Api { .. } => 8,
+            HalpError::Parse(_) => 9,
+            HalpError::SizeLimit(_) => 10,
+        }
+    }
+
+    /// Whether the provider is temporarily unavailable (network failure, timeout,
+    /// rate limit or 5xx), as opposed to rejecting the request itself
+    pub fn is_unavailable(&self) -> bool {
+        match self {
+            HalpError::Network(_) | HalpError::Timeout(_) | HalpError::RateLimit(_) => true,
+            HalpError::Api {
+                status: Some(status),
+                ..
+            } => (500..=599).contains(status),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for HalpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HalpError::Config(message)
+            | HalpError::Auth(message)
+            | HalpError::RateLimit(message) => write!(f, "{}", message),
+            HalpError::Network(message) => write!(f, "Request failed: {}", message),
+            HalpError::Timeout(message) => write!(f, "Request timed out: {}", message),
+            HalpError::Api {
+                status: Some(status),
+                message,
+            } => write!(f, "API error ({}): {}", status, message),
+            HalpError::Api {
+                status: None,
+                message,
+            } => write!(f, "API error: {}", message),
+            HalpError::Parse(message) => write!(f, "Failed to parse API response: {}", message),
+            HalpError::SizeLimit(max_size) => write!(f, "Response too large (>{} bytes)", max_size),
+        }
+    }
+}
+
+impl std::error::Error for HalpError {}
+
+impl From<reqwest::Error> for HalpError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            HalpError::Timeout(e.to_string())
+        } else {
+            HalpError::Network(e.to_string())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_status() {
+        assert!(matches!(
+            HalpError::from_status(StatusCode::UNAUTHORIZED, String::new()),
+            HalpError::Auth(_)
+        ));
+        assert!(matches!(
+            HalpError::from_status(StatusCode::FORBIDDEN, String::new()),
+            HalpError::Auth(_)
+        ));
+        assert!(matches!(
+            HalpError::from_status(StatusCode::TOO_MANY_REQUESTS, String::new()),
+            HalpError::RateLimit(_)
+        ));
+        assert_eq!(
+            HalpError::from_status(StatusCode::BAD_REQUEST, "bad model".to_string()),
+            HalpError::Api {
+                status: Some(400),
+                message: "bad model".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_is_unavailable() {
+        assert!(HalpError::Network("refused".to_string()).is_unavailable());
+        assert!(HalpError::Timeout("slow".to_string()).is_unavailable());
+        assert!(HalpError::RateLimit("429".to_string()).is_unavailable());
+        assert!(HalpError::Api {
+            status: Some(529),
+            message: "overloaded".to_string()
+        }
+        .is_unavailable());
+        assert!(!HalpError::Api {
+            status: Some(400),
+            message: "bad".to_string()
+        }
+        .is_unavailable());
+        assert!(!HalpError::Auth("401".to_string()).is_unavailable());
+        assert!(!HalpError::Parse("eof".to_string()).is_unavailable());
+    }
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        let errors = [
+            HalpError::Config(String::new()),
+            HalpError::Auth(String::new()),
+            HalpError::RateLimit(String::new()),
+            HalpError::Network(String::new()),
+            HalpError::Timeout(String::new()),
+            HalpError::Api {
+                status: None,
+                message: String::new(),
+            },
+            HalpError::Parse(String::new()),
+            HalpError::SizeLimit(0),
+        ];
+        let mut codes: Vec<u8> = errors.iter().map(|e| e.exit_code()).collect();
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), errors.len());
+        assert!(!codes.contains(&1) && !codes.contains(&2));
+    }
+}
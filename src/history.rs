@@ -0,0 +1,259 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// One generated command, as recorded in the history file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub query: String,
+    pub command: String,
+    pub explanation: Option<String>,
+    pub provider: String,
+    pub model: String,
+}
+
+impl Entry {
+    /// An entry for a command generated just now
+    pub fn new(
+        query: &str,
+        command: &str,
+        explanation: Option<&str>,
+        provider: &str,
+        model: &str,
+    ) -> Self {
+        Entry {
+            timestamp: now(),
+            query: query.to_string(),
+            command: command.to_string(),
+            explanation: explanation.map(str::to_string),
+            provider: provider.to_string(),
+            model: model.to_string(),
+        }
+    }
+
+    /// How long ago the entry was recorded, e.g. "3h ago"
+    pub fn age(&self) -> String {
+        format_age(now().saturating_sub(self.timestamp))
+    }
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+/// The history file: one JSON entry per line, oldest first
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    /// The history at `$HALP_HISTORY`, or `history.jsonl` in the XDG data
+    /// directory (`~/.local/share/halp` by default)
+    pub fn open() -> Option<Self> {
+        let path = std::env::var("HALP_HISTORY")
+            .map(PathBuf::from)
+            .ok()
+            .or_else(|| Some(data_dir()?.join("history.jsonl")))?;
+        Some(Self::at(path))
+    }
+
+    pub fn at(path: impl Into<PathBuf>) -> Self {
+        History { path: path.into() }
+    }
+
+    pub fn record(&self, entry: &Entry) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut line = serde_json::to_string(entry).map_err(io::Error::other)?;
+        line.push('\n');
+        // A single write, so concurrent sessions don't interleave lines
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(line.as_bytes())
+    }
+
+    /// All entries, oldest first. Lines that don't parse (say, from a write
+    /// that was cut short) are skipped.
+    pub fn entries(&self) -> io::Result<Vec<Entry>> {
+        let content = match fs::read_to_string(&self.path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        Ok(content
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+}
+
+/// `$XDG_DATA_HOME/halp`, falling back to `~/.local/share/halp`
+fn data_dir() -> Option<PathBuf> {
+    let data_dir = std::env::var("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .ok()
+        .or_else(|| dirs::home_dir().map(|h| h.join(".local").join("share")))?;
+
+    Some(data_dir.join("halp"))
+}
+
+/// Entries whose query or command fuzzy-matches every term, best match
+/// first and newest first among equal matches. Each command is listed once,
+/// for its latest matching entry.
+pub fn search<'a>(entries: &'a [Entry], terms: &[String]) -> Vec<&'a Entry> {
+    let mut seen = HashSet::new();
+    let mut matches: Vec<(u32, &Entry)> = entries
+        .iter()
+        .rev()
+        .filter_map(|entry| {
+            let text = format!("{} {}", entry.query, entry.command);
+            terms
+                .iter()
+                .map(|term| fuzzy_score(term, &text))
+                .sum::<Option<u32>>()
+                .map(|score| (score, entry))
+        })
+        .filter(|(_, entry)| seen.insert(entry.command.as_str()))
+        .collect();
+    // Stable, so ties stay newest first
+    matches.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    matches.into_iter().map(|(_, entry)| entry).collect()
+}
+
+/// How well `term` matches `text`, ignoring case: as a whole word, then
+/// anywhere, then as its characters in order with gaps between them, which
+/// cost a point each. `None` if the characters don't all appear in order.
+fn fuzzy_score(term: &str, text: &str) -> Option<u32> {
+    let term = term.to_lowercase();
+    let text = text.to_lowercase();
+    if term.is_empty() {
+        return Some(0);
+    }
+
+    if let Some(start) = text.find(&term) {
+        let end = start + term.len();
+        let is_boundary = |c: Option<char>| c.is_none_or(|c| !c.is_alphanumeric());
+        let whole_word = is_boundary(text[..start].chars().next_back())
+            && is_boundary(text[end..].chars().next());
+        return Some(if whole_word { 120 } else { 100 });
+    }
+
+    let mut gaps = 0;
+    let mut chars = text.chars();
+    for wanted in term.chars() {
+        loop {
+            match chars.next() {
+                Some(c) if c == wanted => break,
+                Some(_) => gaps += 1,
+                None => return None,
+            }
+        }
+    }
+    Some(60u32.saturating_sub(gaps).max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(timestamp: u64, query: &str, command: &str) -> Entry {
+        Entry {
+            timestamp,
+            query: query.to_string(),
+            command: command.to_string(),
+            explanation: None,
+            provider: "ollama".to_string(),
+            model: "llama3.2".to_string(),
+        }
+    }
+
+    fn terms(terms: &str) -> Vec<String> {
+        terms.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_fuzzy_score() {
+        assert_eq!(fuzzy_score("large", "find large files"), Some(120));
+        assert_eq!(fuzzy_score("LARG", "find large files"), Some(100));
+        assert!(fuzzy_score("lrgfl", "find large files").is_some_and(|s| s < 100));
+        assert_eq!(fuzzy_score("zip", "find large files"), None);
+    }
+
+    #[test]
+    fn test_search() {
+        let entries = vec![
+            entry(1, "find large files", "find . -size +100M"),
+            entry(2, "disk usage by directory", "du -sh * | sort -h"),
+            entry(3, "find big files", "find . -size +100M"),
+            entry(4, "list files", "ls -la"),
+        ];
+
+        let found = search(&entries, &terms("find files"));
+        assert_eq!(found.len(), 1);
+        // The latest entry for the command
+        assert_eq!(found[0].query, "find big files");
+
+        let found = search(&entries, &terms("du"));
+        assert_eq!(found[0].command, "du -sh * | sort -h");
+
+        // Without terms, everything newest first
+        let found: Vec<_> = search(&entries, &[]).iter().map(|e| e.timestamp).collect();
+        assert_eq!(found, vec![4, 3, 2]);
+    }
+
+    #[test]
+    fn test_search_finds_older_entry_for_command() {
+        // A newer entry for the same command whose query doesn't match
+        let entries = vec![
+            entry(1, "find large files", "find . -size +100M"),
+            entry(2, "big stuff", "find . -size +100M"),
+        ];
+
+        let found = search(&entries, &terms("large"));
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].query, "find large files");
+    }
+
+    #[test]
+    fn test_record_and_read_back() {
+        let dir = std::env::temp_dir().join(format!("halp-history-test-{}", std::process::id()));
+        let history = History::at(dir.join("history.jsonl"));
+        assert_eq!(history.entries().unwrap(), Vec::new());
+
+        let first = entry(1, "list files", "ls -la");
+        let second = entry(2, "disk usage", "du -sh");
+        history.record(&first).unwrap();
+        history.record(&second).unwrap();
+        assert_eq!(history.entries().unwrap(), vec![first, second]);
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(5), "just now");
+        assert_eq!(format_age(150), "2m ago");
+        assert_eq!(format_age(7200), "2h ago");
+        assert_eq!(format_age(3 * 86400), "3d ago");
+    }
+}
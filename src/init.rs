@@ -0,0 +1,217 @@
+/// A shell that `halp init` has integration for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+    Nushell,
+    Elvish,
+    PowerShell,
+}
+
+impl Shell {
+    pub const ALL: [Shell; 6] = [
+        Shell::Bash,
+        Shell::Zsh,
+        Shell::Fish,
+        Shell::Nushell,
+        Shell::Elvish,
+        Shell::PowerShell,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Fish => "fish",
+            Shell::Nushell => "nushell",
+            Shell::Elvish => "elvish",
+            Shell::PowerShell => "powershell",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Shell> {
+        Self::ALL.into_iter().find(|shell| shell.name() == name)
+    }
+
+    /// The integration script to load from the shell's startup file. Each
+    /// one defines `h <request>`, which puts the command on the prompt to
+    /// review, and binds Ctrl-G to replace the command line's text with a
+    /// command for it.
+    pub fn script(self) -> &'static str {
+        match self {
+            Shell::Bash => include_str!("init/halp.bash"),
+            Shell::Zsh => include_str!("init/halp.zsh"),
+            Shell::Fish => include_str!("init/halp.fish"),
+            Shell::Nushell => include_str!("init/halp.nu"),
+            Shell::Elvish => include_str!("init/halp.elv"),
+            Shell::PowerShell => include_str!("init/halp.ps1"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+    use std::process::{Command, Output, Stdio};
+
+    #[test]
+    fn test_names() {
+        for shell in Shell::ALL {
+            assert_eq!(Shell::from_name(shell.name()), Some(shell));
+        }
+        assert_eq!(Shell::from_name("tcsh"), None);
+    }
+
+    /// A directory with a fake `halp` that prints its arguments as a
+    /// command and exits with `status`, to put first on `PATH`
+    fn fake_halp(name: &str, status: u8) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("halp-init-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("halp");
+        fs::write(
+            &path,
+            format!(
+                "#!/bin/sh\necho 'explanation' >&2\necho \"echo $*\"\nexit {}\n",
+                status
+            ),
+        )
+        .unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        dir
+    }
+
+    /// Load the script in `bash` and run `test` with `input` on stdin, or
+    /// `None` without bash
+    fn bash(dir: &Path, test: &str, input: &str) -> Option<Output> {
+        let path = format!(
+            "{}:{}",
+            dir.display(),
+            std::env::var("PATH").unwrap_or_default()
+        );
+        let mut child = Command::new("bash")
+            .arg("-c")
+            .arg(format!("{}\n{}", Shell::Bash.script(), test))
+            .env("PATH", path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .ok()?;
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .unwrap();
+        child.wait_with_output().ok()
+    }
+
+    #[test]
+    fn test_bash_widget() {
+        let dir = fake_halp("bash", 0);
+        let Some(output) = bash(
+            &dir,
+            r#"READLINE_LINE='list *.rs files'; READLINE_POINT=3; _halp_widget; printf '%s|%s' "$READLINE_LINE" "$READLINE_POINT""#,
+            "",
+        ) else {
+            return;
+        };
+        let expected = "echo --no-stdin -- list *.rs files";
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            format!("{}|{}", expected, expected.len())
+        );
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_bash_widget_keeps_line_on_failure() {
+        // Exit status 4 (authentication error) prints nothing usable
+        let dir = fake_halp("bash-failure", 4);
+        let Some(output) = bash(
+            &dir,
+            r#"READLINE_LINE='list files'; _halp_widget; printf '%s' "$READLINE_LINE""#,
+            "",
+        ) else {
+            return;
+        };
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "list files");
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_bash_h_runs_edited_command() {
+        let dir = fake_halp("bash-h", 0);
+        // With stdin that isn't a terminal, `read` takes the line as typed
+        let Some(output) = bash(
+            &dir,
+            "_halp_flags=; _halp list files; history",
+            "echo ran\n",
+        ) else {
+            return;
+        };
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.starts_with("ran\n"), "{}", stdout);
+        assert!(stdout.trim_end().ends_with("echo ran"), "{}", stdout);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_scripts_parse() {
+        let dir = std::env::temp_dir().join(format!("halp-init-parse-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for shell in Shell::ALL {
+            let path = dir.join(format!("halp.{}", shell.name()));
+            fs::write(&path, shell.script()).unwrap();
+            let path = path.display().to_string();
+
+            // Each shell's own syntax check, for the shells that are installed
+            let (program, args) = match shell {
+                Shell::Bash => ("bash", vec!["-n".to_string(), path]),
+                Shell::Zsh => ("zsh", vec!["-n".to_string(), path]),
+                Shell::Fish => ("fish", vec!["--no-execute".to_string(), path]),
+                Shell::Nushell => (
+                    "nu",
+                    vec![
+                        "--no-config-file".to_string(),
+                        "--commands".to_string(),
+                        format!("if not (nu-check '{}') {{ exit 1 }}", path),
+                    ],
+                ),
+                Shell::Elvish => ("elvish", vec!["-compileonly".to_string(), path]),
+                Shell::PowerShell => (
+                    "pwsh",
+                    vec![
+                        "-NoProfile".to_string(),
+                        "-NonInteractive".to_string(),
+                        "-Command".to_string(),
+                        format!(
+                            "$errors = $null; \
+                             [System.Management.Automation.Language.Parser]::ParseFile('{}', [ref]$null, [ref]$errors) | Out-Null; \
+                             if ($errors) {{ $errors; exit 1 }}",
+                            path
+                        ),
+                    ],
+                ),
+            };
+            let Ok(output) = Command::new(program).args(&args).output() else {
+                continue;
+            };
+            assert!(
+                output.status.success(),
+                "{} script doesn't parse: {}{}",
+                program,
+                String::from_utf8_lossy(&output.stdout),
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        let _ = fs::remove_dir_all(dir);
+    }
+}
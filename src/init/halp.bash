@@ -0,0 +1,45 @@
+# halp shell integration for bash. Load it from ~/.bashrc with:
+#
+#     eval "$(halp init bash)"
+#
+# h <request>   Generate a command and put it on an editable line. Enter runs
+#               it and adds it to the history.
+# Ctrl-G        Replace the text on the command line with a command for it.
+
+# Whether halp printed a command to use: it succeeded, or the provider couldn't
+# be reached (6 or 7) and it printed an offline suggestion
+_halp_usable() {
+    [ -n "$2" ] && case $1 in 0 | 6 | 7) true ;; *) false ;; esac
+}
+
+# Globs like * and ? in the request are passed to halp as they are: the alias
+# turns globbing off for the call, and _halp turns it back on unless it was
+# off already
+alias h='_halp_flags=$-; set -f; _halp'
+
+_halp() {
+    [[ $_halp_flags == *f* ]] || set +f
+    local cmd status
+    cmd=$(command halp "$@")
+    status=$?
+    _halp_usable "$status" "$cmd" || return "$status"
+    read -r -e -i "$cmd" cmd || return
+    [ -n "$cmd" ] || return 0
+    history -s "$cmd"
+    eval "$cmd"
+}
+
+_halp_widget() {
+    [ -n "$READLINE_LINE" ] || return
+    local cmd status
+    cmd=$(command halp --no-stdin -- "$READLINE_LINE")
+    status=$?
+    _halp_usable "$status" "$cmd" || return
+    READLINE_LINE=$cmd
+    READLINE_POINT=${#cmd}
+}
+
+if [[ $- == *i* ]]; then
+    bind -m emacs -x '"\C-g": _halp_widget'
+    bind -m vi-insert -x '"\C-g": _halp_widget'
+fi
@@ -0,0 +1,40 @@
+# halp shell integration for fish. Load it from ~/.config/fish/config.fish with:
+#
+#     halp init fish | source
+#
+# h <request>   Generate a command and put it on an editable line. Enter runs
+#               it and adds it to the history.
+# Ctrl-G        Replace the text on the command line with a command for it.
+
+# Whether halp printed a command to use: it succeeded, or the provider couldn't
+# be reached (6 or 7) and it printed an offline suggestion
+function __halp_usable --argument-names code cmd
+    test -n "$cmd"; and contains -- $code 0 6 7
+end
+
+function h --description 'Generate a command with halp, edit it and run it'
+    set -l lines (command halp $argv)
+    set -l code $status
+    set -l cmd (string join \n -- $lines)
+    __halp_usable $code "$cmd"; or return $code
+    read --command "$cmd" cmd; or return
+    test -n "$cmd"; or return 0
+    # `history append` is new in fish 4.0
+    builtin history append -- $cmd 2>/dev/null
+    eval $cmd
+end
+
+function __halp_widget --description 'Replace the command line with a command from halp'
+    set -l query (commandline | string collect)
+    test -n "$query"; or return
+    set -l lines (command halp --no-stdin -- $query)
+    set -l code $status
+    set -l cmd (string join \n -- $lines)
+    if __halp_usable $code "$cmd"
+        commandline --replace -- $cmd
+    end
+    commandline -f repaint
+end
+
+bind \cg __halp_widget
+bind -M insert \cg __halp_widget
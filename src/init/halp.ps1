@@ -0,0 +1,43 @@
+# halp shell integration for PowerShell. Load it from $PROFILE with:
+#
+#     Invoke-Expression (& halp init powershell | Out-String)
+#
+# h <request>   Generate a command and put it on the next prompt to edit and
+#               run.
+# Ctrl+G        Replace the text on the command line with a command for it.
+
+# The command halp prints, or $null when there isn't one to use. halp exits
+# with 6 or 7 when the provider couldn't be reached and it printed an offline
+# suggestion. Its explanation and warnings still go to the terminal.
+function Get-HalpCommand([string[]]$Arguments) {
+    $cmd = (& halp @Arguments) -join "`n"
+    if ($LASTEXITCODE -in 0, 6, 7 -and $cmd) {
+        $cmd
+    }
+}
+
+function h {
+    $cmd = Get-HalpCommand $args
+    if ($cmd) {
+        # PSReadLine only takes input while it's reading, so insert once the
+        # next prompt is up
+        Register-EngineEvent -SourceIdentifier PowerShell.OnIdle -MaxTriggerCount 1 -MessageData $cmd -Action {
+            [Microsoft.PowerShell.PSConsoleReadLine]::Insert($Event.MessageData)
+        } | Out-Null
+    }
+}
+
+Set-PSReadLineKeyHandler -Chord Ctrl+g -BriefDescription Halp -Description 'Replace the command line with a command from halp' -ScriptBlock {
+    $line = $null
+    $cursor = $null
+    [Microsoft.PowerShell.PSConsoleReadLine]::GetBufferState([ref]$line, [ref]$cursor)
+    if (-not $line) {
+        return
+    }
+    Write-Host ''
+    $cmd = Get-HalpCommand @('--no-stdin', '--', $line)
+    if ($cmd) {
+        [Microsoft.PowerShell.PSConsoleReadLine]::Replace(0, $line.Length, $cmd)
+    }
+    [Microsoft.PowerShell.PSConsoleReadLine]::InvokePrompt()
+}
@@ -0,0 +1,221 @@
+//! What `--format json` prints, for editor plugins and other wrappers
+
+use crate::output::ParsedResponse;
+use crate::policy::Policy;
+use crate::providers::Usage;
+use crate::safety::{self, Risk};
+use serde::Serialize;
+
+/// A result, and details about the request that produced it, as one object
+#[derive(Serialize)]
+pub struct JsonOutput<T: Serialize> {
+    #[serde(flatten)]
+    pub result: T,
+    #[serde(flatten)]
+    pub meta: ResponseMeta,
+}
+
+impl<T: Serialize> JsonOutput<T> {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("JSON output is always serializable")
+    }
+}
+
+/// The command part of `--format json`: the top candidate and any others
+#[derive(Serialize)]
+pub struct CommandOutput<'a> {
+    pub command: Option<&'a str>,
+    pub explanation: Option<&'a str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub alternatives: Vec<Alternative<'a>>,
+    /// Whether safety analysis found any of the commands high risk, since a
+    /// wrapper may run an alternative rather than the top command
+    #[serde(skip)]
+    pub high_risk: bool,
+}
+
+/// A lower-ranked candidate, with its own safety warnings and policy notes
+#[derive(Serialize)]
+pub struct Alternative<'a> {
+    pub command: &'a str,
+    pub explanation: Option<&'a str>,
+    pub warnings: Vec<String>,
+}
+
+impl<'a> CommandOutput<'a> {
+    /// The candidates of a response, each checked for safety warnings and
+    /// policy confirmations. Those about the top command are added to
+    /// `warnings`; each alternative carries its own.
+    pub fn new(parsed: &'a ParsedResponse, policy: &Policy, warnings: &mut Vec<String>) -> Self {
+        let mut high_risk = false;
+        let mut check = |command: &str| {
+            let findings = safety::analyze(command);
+            high_risk |= findings.iter().any(|f| f.risk == Risk::High);
+            findings
+                .iter()
+                .map(|f| f.to_string())
+                .chain(policy.confirmation(command))
+                .collect::<Vec<_>>()
+        };
+        if let Some(command) = parsed.command() {
+            warnings.extend(check(command));
+        }
+        let alternatives = parsed
+            .candidates
+            .iter()
+            .skip(1)
+            .map(|candidate| Alternative {
+                command: &candidate.command,
+                explanation: candidate.explanation.as_deref(),
+                warnings: check(&candidate.command),
+            })
+            .collect();
+        CommandOutput {
+            command: parsed.command(),
+            explanation: parsed.explanation(),
+            alternatives,
+            high_risk,
+        }
+    }
+}
+
+/// What `--format json` prints instead when there's no response: the error
+/// message and the exit status halp returns with it
+#[derive(Serialize)]
+pub struct ErrorOutput {
+    pub error: String,
+    pub exit_code: u8,
+}
+
+impl ErrorOutput {
+    pub fn new(error: impl ToString, exit_code: u8) -> Self {
+        ErrorOutput {
+            error: error.to_string(),
+            exit_code,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("JSON output is always serializable")
+    }
+}
+
+/// Details about the request that produced a response
+#[derive(Serialize)]
+pub struct ResponseMeta {
+    pub provider: String,
+    pub model: String,
+    pub latency_ms: u64,
+    pub usage: Option<Usage>,
+    /// Whether the response came from the cache rather than the provider
+    pub cached: bool,
+    pub warnings: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::HalpError;
+    use crate::parse_response;
+    use serde_json::{json, Value};
+
+    fn meta(warnings: Vec<String>) -> ResponseMeta {
+        ResponseMeta {
+            provider: "ollama".to_string(),
+            model: "llama3.2".to_string(),
+            latency_ms: 120,
+            usage: None,
+            cached: false,
+            warnings,
+        }
+    }
+
+    #[test]
+    fn test_command_output() {
+        let parsed = parse_response(
+            "COMMAND: rm -rf /\nEXPLANATION: Delete everything\n\n\
+             COMMAND: cargo clean\nEXPLANATION: Let cargo do it",
+        );
+        let policy = Policy::from_toml("[[rule]]\naction = \"confirm\"\nglob = \"rm *\"").unwrap();
+        let mut warnings = Vec::new();
+
+        let output = CommandOutput::new(&parsed, &policy, &mut warnings);
+
+        assert!(output.high_risk);
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[1].starts_with("Confirm before running"));
+        let value: Value = serde_json::from_str(
+            &JsonOutput {
+                result: output,
+                meta: meta(warnings),
+            }
+            .to_json(),
+        )
+        .unwrap();
+        assert_eq!(value["command"], "rm -rf /");
+        assert_eq!(value["explanation"], "Delete everything");
+        assert_eq!(
+            value["alternatives"],
+            json!([{"command": "cargo clean", "explanation": "Let cargo do it", "warnings": []}])
+        );
+        assert_eq!(value["provider"], "ollama");
+        assert_eq!(value["cached"], false);
+        assert!(value.get("high_risk").is_none());
+    }
+
+    #[test]
+    fn test_alternatives_are_checked() {
+        let parsed = parse_response(
+            "COMMAND: cargo clean\nEXPLANATION: Let cargo do it\n\n\
+             COMMAND: rm -rf /\nEXPLANATION: Delete everything",
+        );
+        let policy = Policy::from_toml("[[rule]]\naction = \"confirm\"\nglob = \"rm *\"").unwrap();
+        let mut warnings = Vec::new();
+
+        let output = CommandOutput::new(&parsed, &policy, &mut warnings);
+
+        assert!(output.high_risk);
+        assert!(warnings.is_empty());
+        let alternative = &output.alternatives[0];
+        assert_eq!(alternative.command, "rm -rf /");
+        assert_eq!(alternative.warnings.len(), 2);
+        assert!(alternative.warnings[0].contains("high risk"));
+        assert!(alternative.warnings[1].starts_with("Confirm before running"));
+    }
+
+    #[test]
+    fn test_error_output() {
+        let error = HalpError::Network("connection refused".to_string());
+
+        let value: Value =
+            serde_json::from_str(&ErrorOutput::new(&error, error.exit_code()).to_json()).unwrap();
+
+        assert_eq!(
+            value,
+            json!({"error": "Request failed: connection refused", "exit_code": 6})
+        );
+    }
+
+    #[test]
+    fn test_no_command() {
+        let parsed = ParsedResponse {
+            candidates: Vec::new(),
+        };
+        let mut warnings = Vec::new();
+
+        let output = CommandOutput::new(&parsed, &Policy::default(), &mut warnings);
+
+        assert!(!output.high_risk);
+        assert!(warnings.is_empty());
+        let value: Value = serde_json::from_str(
+            &JsonOutput {
+                result: output,
+                meta: meta(warnings),
+            }
+            .to_json(),
+        )
+        .unwrap();
+        assert_eq!(value["command"], Value::Null);
+        assert!(value.get("alternatives").is_none());
+    }
+}
@@ -0,0 +1,29 @@
+//! Turn natural language into shell commands with an LLM.
+//!
+//! The `halp` binary is a thin CLI over this crate: load a [`Config`] (or
+//! build one in code), create a provider with [`ProviderBuilder`], send it the
+//! prompt from [`build_system_prompt`] and pull the command out of the reply
+//! with [`parse_response`].
+
+pub mod cache;
+pub mod chat;
+pub mod config;
+pub mod error;
+pub mod history;
+pub mod init;
+pub mod json;
+pub mod offline;
+pub mod output;
+pub mod policy;
+pub mod prompt;
+pub mod providers;
+pub mod run;
+pub mod safety;
+pub mod session;
+
+pub use config::Config;
+pub use error::HalpError;
+pub use output::{parse_breakdown, parse_response, Breakdown, Candidate, ParsedResponse};
+pub use policy::Policy;
+pub use prompt::{build_explain_system_prompt, build_system_prompt};
+pub use providers::{Conversation, LlmProvider, ProviderBuilder, Usage};
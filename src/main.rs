@@ -1,91 +1,571 @@
-mod config;
-mod output;
-mod prompt;
-mod providers;
-
-use clap::Parser;
-use output::{parse_response, NullWriter, Spinner, StderrStreamer};
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use halp::cache::Cache;
+use halp::history::{self, History};
+use halp::init::Shell;
+use halp::json::ErrorOutput;
+use halp::output::{pick_candidate, Candidate};
+use halp::prompt::{
+    build_alternatives_prompt, build_fix_prompt, build_policy_prompt, build_user_prompt,
+    read_context, StdinContext,
+};
+use halp::run;
+use halp::session::{Mode, Session};
+use halp::{build_explain_system_prompt, build_system_prompt, Config, HalpError, Policy};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
 use std::process::ExitCode;
 
 #[derive(Parser)]
 #[command(name = "halp")]
 #[command(version)]
 #[command(about = "Get shell commands from natural language", long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Natural language description of the command you need
-    #[arg(required = true, num_args = 1..)]
+    #[arg(required_unless_present = "interactive", num_args = 1..)]
     query: Vec<String>,
 
-    /// Suppress explanation (command only)
+    /// Start an interactive session to refine the command with follow-ups
     #[arg(short, long)]
+    interactive: bool,
+
+    /// Suppress explanation (command only)
+    #[arg(short, long, global = true)]
     quiet: bool,
 
     /// Show explanation only (no command output)
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     explain: bool,
+
+    /// Query all configured providers at once and use the fastest answer
+    #[arg(long, global = true)]
+    race: bool,
+
+    /// Don't read piped stdin as context for the query
+    #[arg(long, global = true)]
+    no_stdin: bool,
+
+    /// Ask the provider even if there's a cached response
+    #[arg(long, global = true)]
+    no_cache: bool,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Text, global = true)]
+    format: Format,
+
+    /// Ask for confirmation, then run the command with $SHELL
+    #[arg(short = 'x', long, global = true, conflicts_with = "explain")]
+    run: bool,
+
+    /// Like --run, but if the command fails, ask for a fix and retry, up to N runs in all
+    #[arg(
+        long,
+        value_name = "N",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "3",
+        value_parser = clap::value_parser!(u32).range(1..),
+        global = true,
+        conflicts_with = "explain"
+    )]
+    until_success: Option<u32>,
+
+    /// Ask for N ranked alternatives and pick one interactively
+    #[arg(
+        short = 'n',
+        long,
+        value_name = "N",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u8).range(1..=9),
+        global = true
+    )]
+    alternatives: u8,
 }
 
-#[tokio::main]
-async fn main() -> ExitCode {
-    let cli = Cli::parse();
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Format {
+    /// The command on stdout, the explanation streamed to stderr
+    Text,
+    /// A single JSON object on stdout
+    Json,
+}
 
-    // Load configuration
-    let config = match config::Config::load() {
-        Ok(c) => c,
+#[derive(Subcommand)]
+enum Command {
+    /// Suggest a corrected version of a command that failed
+    Fix(FixArgs),
+    /// Break down what an existing command does
+    Explain(ExplainArgs),
+    /// Refine a command interactively with follow-ups (same as -i)
+    Chat(ChatArgs),
+    /// Search earlier queries and print one of their commands again
+    History(HistoryArgs),
+    /// Manage the response cache
+    Cache(CacheArgs),
+    /// Print shell integration to load from your shell's startup file
+    Init(InitArgs),
+}
+
+#[derive(Args)]
+struct FixArgs {
+    /// Exit status the command failed with
+    #[arg(short, long, allow_negative_numbers = true)]
+    status: Option<i32>,
+
+    /// File with the command's captured error output (piped stdin is used otherwise)
+    #[arg(long, value_name = "FILE")]
+    stderr: Option<PathBuf>,
+
+    /// The command line that failed
+    #[arg(required = true, num_args = 1.., trailing_var_arg = true, allow_hyphen_values = true)]
+    command: Vec<String>,
+}
+
+#[derive(Args)]
+struct ExplainArgs {
+    /// The command to explain, quoted (read from stdin if omitted)
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    command: Vec<String>,
+}
+
+#[derive(Args)]
+struct ChatArgs {
+    /// The first request of the session
+    #[arg(trailing_var_arg = true)]
+    query: Vec<String>,
+}
+
+#[derive(Args)]
+struct HistoryArgs {
+    /// Words to fuzzy-search earlier queries and commands for
+    #[arg(trailing_var_arg = true)]
+    terms: Vec<String>,
+
+    /// List the matches instead of choosing one
+    #[arg(short, long)]
+    list: bool,
+
+    /// Most matches to show
+    #[arg(long, default_value_t = 20)]
+    limit: usize,
+}
+
+#[derive(Args)]
+struct CacheArgs {
+    #[command(subcommand)]
+    command: CacheCommand,
+}
+
+#[derive(Subcommand)]
+enum CacheCommand {
+    /// Remove every cached response
+    Clear,
+}
+
+#[derive(Args)]
+struct InitArgs {
+    /// The shell to integrate with
+    #[arg(value_parser = PossibleValuesParser::new(Shell::ALL.map(Shell::name))
+        .map(|name| Shell::from_name(&name).expect("only known shells are accepted")))]
+    shell: Shell,
+}
+
+/// Parse the command line. A query that starts with a subcommand's name
+/// (`halp init a new git repo`) is taken as a query when it doesn't parse as
+/// the subcommand and more than one word follows the name.
+fn parse_args(args: Vec<OsString>) -> Result<Cli, clap::Error> {
+    let error = match Cli::try_parse_from(&args) {
+        Ok(cli) => return Ok(cli),
+        Err(e) => e,
+    };
+    if matches!(
+        error.kind(),
+        ErrorKind::DisplayHelp | ErrorKind::DisplayVersion
+    ) {
+        return Err(error);
+    }
+    let command = Cli::command();
+    let Some(position) = args.iter().skip(1).position(|arg| {
+        command
+            .get_subcommands()
+            .any(|subcommand| arg.to_str() == Some(subcommand.get_name()))
+    }) else {
+        return Err(error);
+    };
+    // Position of the name in `args`, counting the program name
+    let position = position + 1;
+    let words_after = args[position + 1..]
+        .iter()
+        .filter(|arg| !arg.to_string_lossy().starts_with('-'))
+        .count();
+    if words_after < 2 {
+        return Err(error);
+    }
+    // Parse the rest as usual, so options after the query still work, and
+    // put the name back in front of the query
+    let mut rest = args;
+    let name = rest.remove(position);
+    match Cli::try_parse_from(rest) {
+        Ok(mut cli) if cli.command.is_none() => {
+            cli.query.insert(0, name.to_string_lossy().into_owned());
+            Ok(cli)
+        }
+        _ => Err(error),
+    }
+}
+
+/// The words of the natural language query, if any
+fn query_words(cli: &Cli) -> &[String] {
+    match &cli.command {
+        Some(Command::Chat(args)) => &args.query,
+        _ => &cli.query,
+    }
+}
+
+/// The query as recorded in the history
+fn history_query(cli: &Cli) -> String {
+    match &cli.command {
+        Some(Command::Fix(args)) => format!("fix {}", args.command.join(" ")),
+        _ => query_words(cli).join(" "),
+    }
+}
+
+/// Read piped stdin, unless it's a terminal or disabled with --no-stdin
+fn read_stdin(cli: &Cli, limit: usize) -> io::Result<Option<StdinContext>> {
+    if cli.no_stdin || io::stdin().is_terminal() {
+        return Ok(None);
+    }
+    read_context(io::stdin().lock(), limit)
+}
+
+/// Build the user message for the query or subcommand
+fn build_query(cli: &Cli, config: &Config, warnings: &mut Vec<String>) -> Result<String, String> {
+    let limit = config.max_stdin_bytes;
+    let (query, context) = match &cli.command {
+        Some(Command::Fix(args)) => {
+            let stderr = match &args.stderr {
+                Some(path) => File::open(path)
+                    .and_then(|file| read_context(file, limit))
+                    .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?,
+                None => {
+                    read_stdin(cli, limit).map_err(|e| format!("Failed to read stdin: {}", e))?
+                }
+            };
+            let prompt = build_fix_prompt(&args.command.join(" "), args.status, stderr.as_ref());
+            (prompt, stderr)
+        }
+        Some(Command::Explain(args)) if args.command.is_empty() => {
+            let input = read_stdin(cli, limit)
+                .map_err(|e| format!("Failed to read stdin: {}", e))?
+                .ok_or("No command to explain (pass it as an argument or on stdin)")?;
+            (input.content.trim().to_string(), Some(input))
+        }
+        Some(Command::Explain(args)) => (args.command.join(" "), None),
+        Some(Command::History(_) | Command::Cache(_) | Command::Init(_)) => {
+            unreachable!("history, cache and init are handled without asking a provider")
+        }
+        None | Some(Command::Chat(_)) => {
+            // Piped input (e.g. `kubectl get pods | halp ...`) becomes context for the query
+            let context =
+                read_stdin(cli, limit).map_err(|e| format!("Failed to read stdin: {}", e))?;
+            let prompt = build_user_prompt(&query_words(cli).join(" "), context.as_ref());
+            (prompt, context)
+        }
+    };
+
+    if context.is_some_and(|c| c.truncated) {
+        warnings.push(format!(
+            "Input truncated to {} bytes (max_stdin_bytes)",
+            limit
+        ));
+    }
+    Ok(query)
+}
+
+/// `halp history`: list matching entries, or choose one and print its command
+fn show_history(args: &HistoryArgs, json: bool) -> ExitCode {
+    let Some(history) = History::open() else {
+        eprintln!("Error: Could not find a home directory for the history");
+        return ExitCode::FAILURE;
+    };
+    let entries = match history.entries() {
+        Ok(entries) => entries,
         Err(e) => {
-            eprintln!("Configuration error: {}", e);
+            eprintln!("Error: Failed to read the history: {}", e);
             return ExitCode::FAILURE;
         }
     };
+    let mut matches = history::search(&entries, &args.terms);
+    matches.truncate(args.limit);
 
-    // Build the prompt
-    let user_query = cli.query.join(" ");
-    let system_prompt = prompt::build_system_prompt(config.system_prompt.as_deref());
-
-    // Create the provider
-    let provider = providers::create_provider(&config);
+    if json {
+        println!(
+            "{}",
+            serde_json::to_string(&matches).expect("JSON output is always serializable")
+        );
+        return ExitCode::SUCCESS;
+    }
+    if matches.is_empty() {
+        eprintln!("No matching history");
+        return ExitCode::FAILURE;
+    }
+    if args.list {
+        for entry in &matches {
+            println!("{:>9}  {}  # {}", entry.age(), entry.command, entry.query);
+        }
+        return ExitCode::SUCCESS;
+    }
 
-    // Stream the response
-    let response = if cli.quiet {
-        let mut writer = NullWriter;
-        provider
-            .stream_completion(&user_query, &system_prompt, &mut writer)
-            .await
+    // Like alternatives: choose when there's a terminal, otherwise take the best match
+    let chosen = if matches.len() > 1 && io::stderr().is_terminal() {
+        let candidates: Vec<Candidate> = matches
+            .iter()
+            .map(|entry| Candidate {
+                command: entry.command.clone(),
+                explanation: Some(format!("{} ({})", entry.query, entry.age())),
+            })
+            .collect();
+        match pick_candidate(&candidates) {
+            Ok(Some(index)) => matches[index],
+            Ok(None) => {
+                eprintln!("No command selected");
+                return ExitCode::FAILURE;
+            }
+            Err(e) => {
+                eprintln!("Error: Failed to show picker: {}", e);
+                return ExitCode::FAILURE;
+            }
+        }
     } else {
-        let spinner = Spinner::start();
-        let mut writer = StderrStreamer::new(Some(spinner));
-        let result = provider
-            .stream_completion(&user_query, &system_prompt, &mut writer)
-            .await;
-        writer.finish();
-        result
+        matches[0]
     };
+    println!("{}", chosen.command);
+    ExitCode::SUCCESS
+}
 
-    let response = match response {
-        Ok(r) => r,
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            return ExitCode::FAILURE;
+/// `halp cache clear`
+fn manage_cache(args: &CacheArgs) -> ExitCode {
+    let Some(cache) = Cache::open() else {
+        eprintln!("Error: Could not find a home directory for the cache");
+        return ExitCode::FAILURE;
+    };
+    match args.command {
+        CacheCommand::Clear => match cache.clear() {
+            Ok(removed) => {
+                eprintln!("Removed {} cached responses", removed);
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("Error: Failed to clear the cache: {}", e);
+                ExitCode::FAILURE
+            }
+        },
+    }
+}
+
+/// Report a configuration error, as a JSON object on stdout with
+/// `--format json`
+fn config_error(e: &HalpError, json: bool) -> ExitCode {
+    if json {
+        println!("{}", ErrorOutput::new(e, e.exit_code()).to_json());
+    } else {
+        eprintln!("Configuration error: {}", e);
+    }
+    ExitCode::from(e.exit_code())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let cli = parse_args(std::env::args_os().collect()).unwrap_or_else(|e| e.exit());
+    let run_mode = cli.run || cli.until_success.is_some();
+    let interactive = cli.interactive || matches!(cli.command, Some(Command::Chat(_)));
+    if (run_mode || interactive) && !run::SUPPORTED {
+        eprintln!(
+            "Error: --run, --until-success and interactive sessions need a Unix terminal and shell"
+        );
+        return ExitCode::FAILURE;
+    }
+    // Sessions have /run instead of --run
+    if interactive && (cli.format == Format::Json || cli.explain || run_mode) {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "interactive sessions only work with text output, without --run or --explain",
+            )
+            .exit();
+    }
+    if run_mode
+        && (cli.format == Format::Json
+            || matches!(
+                cli.command,
+                Some(
+                    Command::Explain(_)
+                        | Command::History(_)
+                        | Command::Cache(_)
+                        | Command::Init(_)
+                )
+            ))
+    {
+        Cli::command()
+            .error(
+                ErrorKind::ArgumentConflict,
+                "--run and --until-success only work with text output for a generated command",
+            )
+            .exit();
+    }
+
+    // These don't need a provider, so they work without a configuration
+    match &cli.command {
+        Some(Command::History(args)) => return show_history(args, cli.format == Format::Json),
+        Some(Command::Cache(args)) => return manage_cache(args),
+        Some(Command::Init(args)) => {
+            print!("{}", args.shell.script());
+            return ExitCode::SUCCESS;
         }
+        _ => {}
+    }
+
+    let json = cli.format == Format::Json;
+
+    // Load configuration
+    let config = match Config::load() {
+        Ok(c) => c,
+        Err(e) => return config_error(&e, json),
+    };
+
+    let policy = match Policy::load() {
+        Ok(p) => p,
+        Err(e) => return config_error(&e, json),
     };
 
-    // Parse and output the response
-    let parsed = parse_response(&response);
+    let quiet = cli.quiet || json;
+    // Notes for the user: printed to stderr, or included in the JSON output
+    let mut warnings = config.warnings.clone();
 
-    if cli.explain {
-        // Explanation-only mode: output explanation to stdout
-        if let Some(explanation) = parsed.explanation {
-            println!("{}", explanation);
+    // Build the prompt. An interactive session can start without one.
+    let user_query = if interactive && query_words(&cli).is_empty() {
+        None
+    } else {
+        match build_query(&cli, &config, &mut warnings) {
+            Ok(query) => Some(query),
+            Err(e) if json => {
+                println!("{}", ErrorOutput::new(e, 1).to_json());
+                return ExitCode::FAILURE;
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return ExitCode::FAILURE;
+            }
         }
+    };
+    if !quiet {
+        for warning in &warnings {
+            eprintln!("Note: {}", warning);
+        }
+    }
+    let explain_command = matches!(cli.command, Some(Command::Explain(_)));
+    let system_prompt = if explain_command {
+        build_explain_system_prompt()
     } else {
-        // Normal mode: output command to stdout
-        if let Some(command) = parsed.command {
-            println!("{}", command);
-        } else {
-            eprintln!("Could not extract command from response");
-            return ExitCode::FAILURE;
+        let mut prompt =
+            build_system_prompt(config.system_prompt.as_deref()) + &build_policy_prompt(&policy);
+        if cli.alternatives > 1 {
+            prompt = build_alternatives_prompt(&prompt, cli.alternatives as usize);
+        }
+        prompt
+    };
+
+    let mut session = match Session::new(config, policy, system_prompt, cli.race) {
+        Ok(session) => session.query(history_query(&cli)).quiet(cli.quiet),
+        Err(e) => return config_error(&e, json),
+    };
+    // `halp explain` doesn't generate a command, so there's nothing to record
+    if explain_command {
+        session = session.without_history();
+    }
+    if interactive {
+        return ExitCode::from(session.chat(user_query).await);
+    }
+    let user_query = user_query.expect("only interactive sessions start without a query");
+
+    let mode = Mode {
+        json,
+        breakdown: explain_command,
+        explanation_only: cli.explain,
+        run: run_mode.then(|| cli.until_success.unwrap_or(1)),
+        no_cache: cli.no_cache,
+        offline: cli.command.is_none(),
+    };
+    ExitCode::from(session.answer(user_query, &mode, warnings).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<Cli, clap::Error> {
+        parse_args(line.split(' ').map(OsString::from).collect())
+    }
+
+    #[test]
+    fn test_subcommands_take_their_arguments() {
+        let cli = parse("halp fix the tests").unwrap();
+        assert!(
+            matches!(&cli.command, Some(Command::Fix(args)) if args.command == ["the", "tests"])
+        );
+
+        let cli = parse("halp explain how rsync works").unwrap();
+        assert!(matches!(cli.command, Some(Command::Explain(_))));
+        let cli = parse("halp chat about tar flags").unwrap();
+        assert!(matches!(cli.command, Some(Command::Chat(_))));
+        let cli = parse("halp history of git commands").unwrap();
+        assert!(matches!(cli.command, Some(Command::History(_))));
+        let cli = parse("halp init zsh").unwrap();
+        assert!(matches!(cli.command, Some(Command::Init(_))));
+        let cli = parse("halp cache clear").unwrap();
+        assert!(matches!(cli.command, Some(Command::Cache(_))));
+    }
+
+    #[test]
+    fn test_subcommand_words_start_queries() {
+        for line in [
+            "halp init a new git repo",
+            "halp cache the npm packages",
+            "halp -q init a git repo",
+        ] {
+            let cli = parse(line).unwrap();
+            assert!(cli.command.is_none(), "{}", line);
+            assert_eq!(
+                cli.query.join(" "),
+                line.trim_start_matches("halp -q ")
+                    .trim_start_matches("halp ")
+            );
         }
+        let cli = parse("halp init a new git repo --no-stdin").unwrap();
+        assert_eq!(cli.query, ["init", "a", "new", "git", "repo"]);
+        assert!(cli.no_stdin);
+        let cli = parse("halp list init scripts").unwrap();
+        assert!(cli.command.is_none());
+        let cli = parse("halp -- fix the tests").unwrap();
+        assert_eq!(cli.query, ["fix", "the", "tests"]);
+        let cli = parse_args(vec!["halp".into(), "fix the tests".into()]).unwrap();
+        assert_eq!(cli.query, ["fix the tests"]);
     }
 
-    ExitCode::SUCCESS
+    #[test]
+    fn test_short_subcommand_errors_are_kept() {
+        assert!(parse("halp init fsh").is_err());
+        assert!(parse("halp cache stats").is_err());
+        assert_eq!(
+            parse("halp init --help").err().map(|e| e.kind()),
+            Some(ErrorKind::DisplayHelp)
+        );
+    }
 }
@@ -0,0 +1,353 @@
+use crate::history::Entry;
+use std::collections::HashSet;
+
+/// A common command and what it's for, matched against queries when the
+/// provider can't be reached
+struct Recipe {
+    description: &'static str,
+    command: &'static str,
+}
+
+const fn recipe(description: &'static str, command: &'static str) -> Recipe {
+    Recipe {
+        description,
+        command,
+    }
+}
+
+const RECIPES: &[Recipe] = &[
+    // tar
+    recipe(
+        "compress a directory into a tar.gz archive",
+        "tar -czvf archive.tar.gz directory/",
+    ),
+    recipe("extract a tar.gz archive", "tar -xzvf archive.tar.gz"),
+    recipe(
+        "extract a tar archive into a specific directory",
+        "tar -xvf archive.tar -C /path/to/dir",
+    ),
+    recipe(
+        "list the contents of a tar archive without extracting",
+        "tar -tzvf archive.tar.gz",
+    ),
+    recipe(
+        "compress a directory excluding node_modules",
+        "tar -czvf archive.tar.gz --exclude='node_modules' .",
+    ),
+    recipe(
+        "compress with xz into a tar.xz archive",
+        "tar -cJvf archive.tar.xz directory/",
+    ),
+    recipe("extract a tar.bz2 archive", "tar -xjvf archive.tar.bz2"),
+    // find
+    recipe(
+        "find large files bigger than 100MB",
+        "find . -type f -size +100M",
+    ),
+    recipe("find files by name", "find . -name '*.txt'"),
+    recipe(
+        "find files modified in the last day",
+        "find . -type f -mtime -1",
+    ),
+    recipe("find empty files and directories", "find . -empty"),
+    recipe(
+        "delete files older than 30 days",
+        "find . -type f -mtime +30 -delete",
+    ),
+    recipe("search for text in files recursively", "grep -rn 'text' ."),
+    recipe(
+        "count lines in all files of a type",
+        "find . -name '*.rs' | xargs wc -l",
+    ),
+    recipe("find directories by name", "find . -type d -name 'name'"),
+    recipe(
+        "find and delete empty directories",
+        "find . -type d -empty -delete",
+    ),
+    recipe(
+        "show disk usage of directories sorted by size",
+        "du -sh * | sort -h",
+    ),
+    // rsync
+    recipe(
+        "sync files to a remote server preserving permissions",
+        "rsync -avz ./ user@remote:/path/",
+    ),
+    recipe(
+        "mirror a directory, deleting extra files",
+        "rsync -av --delete source/ destination/",
+    ),
+    recipe(
+        "copy files with progress",
+        "rsync -ah --progress source destination",
+    ),
+    recipe(
+        "dry run rsync to preview what would be copied",
+        "rsync -avn source/ destination/",
+    ),
+    recipe(
+        "sync files from a remote server",
+        "rsync -avz user@remote:/path/ ./",
+    ),
+    recipe(
+        "resume an interrupted file transfer",
+        "rsync -avP source destination",
+    ),
+    // git
+    recipe(
+        "undo the last commit but keep the changes",
+        "git reset --soft HEAD~1",
+    ),
+    recipe("discard local changes to a file", "git restore file"),
+    recipe(
+        "amend the last commit message",
+        "git commit --amend -m 'new message'",
+    ),
+    recipe(
+        "create and switch to a new branch",
+        "git switch -c new-branch",
+    ),
+    recipe("delete a local branch", "git branch -d branch"),
+    recipe("delete a remote branch", "git push origin --delete branch"),
+    recipe(
+        "show the commit log as a graph",
+        "git log --oneline --graph --all",
+    ),
+    recipe(
+        "stash uncommitted changes",
+        "git stash push -m 'work in progress'",
+    ),
+    recipe("apply the latest stash", "git stash pop"),
+    recipe("unstage a staged file", "git restore --staged file"),
+    recipe("show changes staged for commit", "git diff --staged"),
+    recipe(
+        "rebase the current branch onto main",
+        "git fetch origin && git rebase origin/main",
+    ),
+    recipe(
+        "list branches sorted by last commit date",
+        "git branch --sort=-committerdate",
+    ),
+    recipe(
+        "show who last changed each line of a file",
+        "git blame file",
+    ),
+    recipe("remove untracked files and directories", "git clean -fd"),
+    // docker
+    recipe("list running docker containers", "docker ps"),
+    recipe(
+        "list all docker containers including stopped",
+        "docker ps -a",
+    ),
+    recipe(
+        "stop all running docker containers",
+        "docker stop $(docker ps -q)",
+    ),
+    recipe(
+        "remove unused docker images, containers and volumes",
+        "docker system prune -a --volumes",
+    ),
+    recipe(
+        "open a shell inside a running docker container",
+        "docker exec -it container sh",
+    ),
+    recipe(
+        "follow the logs of a docker container",
+        "docker logs -f container",
+    ),
+    recipe(
+        "build a docker image from the current directory",
+        "docker build -t image:latest .",
+    ),
+    recipe(
+        "run a docker container with a port mapped",
+        "docker run -d -p 8080:80 image",
+    ),
+    recipe("list docker images", "docker images"),
+    recipe(
+        "show cpu and memory usage of docker containers",
+        "docker stats",
+    ),
+    recipe(
+        "copy a file out of a docker container",
+        "docker cp container:/path/to/file .",
+    ),
+];
+
+/// Words that say little about which command is wanted
+const STOP_WORDS: &[&str] = &[
+    "a", "all", "an", "and", "are", "by", "for", "from", "how", "i", "in", "into", "is", "it",
+    "me", "my", "of", "on", "or", "the", "this", "to", "with",
+];
+
+/// Where an offline suggestion came from
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    /// A command generated earlier for a similar query
+    History(Entry),
+    /// One of the bundled recipes
+    Recipe,
+}
+
+/// A command found without the provider, for a query like the user's
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub command: String,
+    pub explanation: Option<String>,
+    pub source: Source,
+}
+
+/// How similar queries have to be for a suggestion, from 0 to 1
+const MIN_SIMILARITY: f64 = 0.5;
+
+/// Suggestions for the query from the history and the bundled recipes,
+/// most similar first and each command once. Earlier queries win ties, since
+/// they're the user's own, and newer ones win among those.
+pub fn suggest(query: &str, entries: &[Entry]) -> Vec<Suggestion> {
+    let from_history = entries.iter().rev().map(|entry| {
+        let suggestion = Suggestion {
+            command: entry.command.clone(),
+            explanation: entry.explanation.clone(),
+            source: Source::History(entry.clone()),
+        };
+        (similarity(query, &entry.query), suggestion)
+    });
+    let from_recipes = RECIPES.iter().map(|recipe| {
+        let suggestion = Suggestion {
+            command: recipe.command.to_string(),
+            explanation: Some(capitalize(recipe.description)),
+            source: Source::Recipe,
+        };
+        (similarity(query, recipe.description), suggestion)
+    });
+
+    let mut suggestions: Vec<(f64, Suggestion)> = from_history
+        .chain(from_recipes)
+        .filter(|(score, _)| *score >= MIN_SIMILARITY)
+        .collect();
+    // Stable, so history stays ahead of recipes on ties
+    suggestions.sort_by(|a, b| b.0.total_cmp(&a.0));
+    let mut seen = HashSet::new();
+    suggestions
+        .into_iter()
+        .map(|(_, s)| s)
+        .filter(|s| seen.insert(s.command.clone()))
+        .collect()
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+        .filter(|word| !word.is_empty() && !STOP_WORDS.contains(&word.as_str()))
+        .collect()
+}
+
+/// How well `text` matches the query, from 0 to 1: mostly the share of the
+/// query's words with a close match in the text, and some of the reverse,
+/// so shorter texts win between ones that cover the query equally
+fn similarity(query: &str, text: &str) -> f64 {
+    let query = words(query);
+    let text = words(text);
+    if query.is_empty() || text.is_empty() {
+        return 0.0;
+    }
+    0.7 * coverage(&query, &text) + 0.3 * coverage(&text, &query)
+}
+
+/// The share of `words` with a close match among `others`
+fn coverage(words: &[String], others: &[String]) -> f64 {
+    let matched = words
+        .iter()
+        .filter(|word| others.iter().any(|other| is_close(word, other)))
+        .count();
+    matched as f64 / words.len() as f64
+}
+
+/// Whether two words are close: one starts with the other ("big" and
+/// "bigger"), or they share most of their letter pairs ("directories" and
+/// "directory")
+fn is_close(a: &str, b: &str) -> bool {
+    if a == b {
+        return true;
+    }
+    let (shorter, longer) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    if shorter.len() >= 3 && longer.starts_with(shorter) {
+        return true;
+    }
+    let pairs = |word: &str| -> HashSet<(char, char)> {
+        let chars: Vec<char> = word.chars().collect();
+        chars.windows(2).map(|w| (w[0], w[1])).collect()
+    };
+    let a = pairs(a);
+    let b = pairs(b);
+    if a.is_empty() || b.is_empty() {
+        return false;
+    }
+    // Dice coefficient
+    2.0 * a.intersection(&b).count() as f64 / (a.len() + b.len()) as f64 >= 0.7
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(similarity("list files", "list files"), 1.0);
+        assert!(similarity("find big files", "find large files bigger than 100MB") > 0.5);
+        assert!(
+            similarity(
+                "compress directories",
+                "compress a directory into a tar.gz archive"
+            ) > 0.5
+        );
+        assert_eq!(similarity("restart nginx", "list docker images"), 0.0);
+        assert_eq!(similarity("the", "a"), 0.0);
+    }
+
+    #[test]
+    fn test_suggest_recipe() {
+        let suggestions = suggest("extract a tar.gz file", &[]);
+        assert_eq!(suggestions[0].command, "tar -xzvf archive.tar.gz");
+        assert_eq!(suggestions[0].source, Source::Recipe);
+
+        let suggestions = suggest("undo my last commit", &[]);
+        assert_eq!(suggestions[0].command, "git reset --soft HEAD~1");
+
+        assert!(suggest("play some music", &[]).is_empty());
+    }
+
+    #[test]
+    fn test_history_comes_first() {
+        let entry = Entry {
+            timestamp: 1,
+            query: "list running docker containers".to_string(),
+            command: "docker ps --format '{{.Names}}'".to_string(),
+            explanation: None,
+            provider: "ollama".to_string(),
+            model: "llama3.2".to_string(),
+        };
+        // The same command later, for a query that matches less well
+        let later = Entry {
+            timestamp: 2,
+            query: "container names".to_string(),
+            ..entry.clone()
+        };
+        let suggestions = suggest("list running docker containers", &[entry.clone(), later]);
+        let command = entry.command.clone();
+        assert_eq!(suggestions[0].source, Source::History(entry));
+        assert_eq!(suggestions[1].command, "docker ps");
+        assert_eq!(
+            suggestions.iter().filter(|s| s.command == command).count(),
+            1
+        );
+    }
+}
@@ -1,11 +1,17 @@
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{self, Clear, ClearType};
+use crossterm::{cursor, queue};
+use serde::Serialize;
+use std::fmt;
 use std::io::{self, Write};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
 // ANSI escape codes for styling
-const DIM: &str = "\x1b[2m";
-const RESET: &str = "\x1b[0m";
+pub(crate) const DIM: &str = "\x1b[2m";
+pub(crate) const BOLD: &str = "\x1b[1m";
+pub(crate) const RESET: &str = "\x1b[0m";
 
 const SPINNER_FRAMES: &[char] = &['⣾', '⣽', '⣻', '⢿', '⡿', '⣟', '⣯', '⣷'];
 
@@ -38,51 +44,74 @@ impl Spinner {
     }
 }
 
-pub struct ParsedResponse {
-    pub command: Option<String>,
+/// One suggested command. Responses can hold several, ranked best first.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Candidate {
+    pub command: String,
     pub explanation: Option<String>,
 }
 
+pub struct ParsedResponse {
+    pub candidates: Vec<Candidate>,
+}
+
+impl ParsedResponse {
+    /// The top-ranked command
+    pub fn command(&self) -> Option<&str> {
+        self.candidates.first().map(|c| c.command.as_str())
+    }
+
+    /// Explanation of the top-ranked command
+    pub fn explanation(&self) -> Option<&str> {
+        self.candidates.first()?.explanation.as_deref()
+    }
+}
+
 pub fn parse_response(response: &str) -> ParsedResponse {
-    let mut command = None;
-    let mut explanation = None;
-
-    // Look for COMMAND: prefix
-    if let Some(cmd_start) = response.find("COMMAND:") {
-        let after_prefix = &response[cmd_start + 8..];
-        // Command ends at newline or EXPLANATION:
-        let cmd_end = after_prefix
-            .find('\n')
-            .or_else(|| after_prefix.find("EXPLANATION:"))
-            .unwrap_or(after_prefix.len());
-        let cmd = after_prefix[..cmd_end].trim();
-        if !cmd.is_empty() {
-            command = Some(cmd.to_string());
+    let mut candidates = Vec::new();
+    let mut pos = 0;
+
+    // Each COMMAND: prefix at the start of a line starts a candidate
+    while let Some(found) = find_command(response, pos) {
+        let cmd_start = found + 8;
+        let block = extract_command(&response[cmd_start..]);
+        let command_end = cmd_start + block.end;
+
+        // Its explanation runs up to the next candidate
+        let next = find_command(response, command_end).unwrap_or(response.len());
+        let explanation = find_explanation(&response[command_end..next]);
+
+        if !block.text.is_empty() {
+            candidates.push(Candidate {
+                command: block.text,
+                explanation,
+            });
         }
+        pos = next;
     }
 
-    // Look for EXPLANATION: prefix
-    if let Some(exp_start) = response.find("EXPLANATION:") {
-        let after_prefix = &response[exp_start + 12..];
-        let exp = after_prefix.trim();
-        if !exp.is_empty() {
-            explanation = Some(exp.to_string());
+    // An explanation given before the first command still counts
+    if let Some(first) = candidates.first_mut() {
+        if first.explanation.is_none() {
+            let first_start = find_command(response, 0).unwrap_or(0);
+            first.explanation = find_explanation(&response[..first_start]);
         }
+        return ParsedResponse { candidates };
     }
 
     // Fallback: if no COMMAND: found, try to extract a code block or the first line
-    if command.is_none() {
-        // Try to find a code block
-        if let Some(code_start) = response.find("```") {
-            let after_backticks = &response[code_start + 3..];
-            // Skip language identifier if present
-            let content_start = after_backticks.find('\n').map(|i| i + 1).unwrap_or(0);
-            let content = &after_backticks[content_start..];
-            if let Some(code_end) = content.find("```") {
-                let cmd = content[..code_end].trim();
-                if !cmd.is_empty() {
-                    command = Some(cmd.to_string());
-                }
+    let mut command = None;
+
+    // Try to find a code block
+    if let Some(code_start) = response.find("```") {
+        let after_backticks = &response[code_start + 3..];
+        // Skip language identifier if present
+        let content_start = after_backticks.find('\n').map(|i| i + 1).unwrap_or(0);
+        let content = &after_backticks[content_start..];
+        if let Some(code_end) = content.find("```") {
+            let cmd = content[..code_end].trim();
+            if !cmd.is_empty() {
+                command = Some(cmd.to_string());
             }
         }
     }
@@ -95,8 +124,482 @@ pub fn parse_response(response: &str) -> ParsedResponse {
     }
 
     ParsedResponse {
-        command,
-        explanation,
+        candidates: command
+            .map(|command| Candidate {
+                command,
+                explanation: find_explanation(response),
+            })
+            .into_iter()
+            .collect(),
+    }
+}
+
+/// Offset of the first `COMMAND:` from `from` on that starts a line, after
+/// any indentation. One mentioned in the middle of a sentence isn't a candidate.
+fn find_command(response: &str, from: usize) -> Option<usize> {
+    response[from..]
+        .match_indices("COMMAND:")
+        .map(|(i, _)| from + i)
+        .find(|&i| {
+            let before = response[..i].trim_end_matches([' ', '\t']);
+            before.is_empty() || before.ends_with('\n')
+        })
+}
+
+/// Everything after the first EXPLANATION: prefix
+fn find_explanation(text: &str) -> Option<String> {
+    let exp_start = text.find("EXPLANATION:")?;
+    let exp = text[exp_start + 12..].trim();
+    if exp.is_empty() {
+        None
+    } else {
+        Some(exp.to_string())
+    }
+}
+
+/// A command pulled out of the text following `COMMAND:`
+struct CommandBlock {
+    text: String,
+    /// Byte offset just past the command
+    end: usize,
+    /// Whether the end of the command was seen, rather than the text running out
+    complete: bool,
+}
+
+/// Whether a (possibly partial) response already contains the whole command
+pub(crate) fn has_complete_command(response: &str) -> bool {
+    match find_command(response, 0) {
+        Some(start) => {
+            let block = extract_command(&response[start + 8..]);
+            block.complete && !block.text.is_empty()
+        }
+        None => false,
+    }
+}
+
+/// Extract the command at the start of `text`. It runs to the end of the line,
+/// unless the line ends in a backslash continuation or opens a heredoc, or the
+/// command is fenced with triple backticks.
+fn extract_command(text: &str) -> CommandBlock {
+    let first_line_end = text.find('\n').unwrap_or(text.len());
+
+    // The command may start on the line after `COMMAND:`
+    let start = if text[..first_line_end].trim().is_empty() {
+        let next = (first_line_end + 1).min(text.len());
+        let blank = text[next..].len() - text[next..].trim_start().len();
+        if text[next + blank..].starts_with("EXPLANATION:") {
+            0
+        } else {
+            next + blank
+        }
+    } else {
+        0
+    };
+
+    let rest = &text[start..];
+    let indent = rest.len() - rest.trim_start_matches([' ', '\t']).len();
+    if rest[indent..].starts_with("```") {
+        return extract_fenced(text, start + indent + 3);
+    }
+
+    // Single line with no newline at all: the explanation may follow on the same line
+    if !rest.contains('\n') {
+        let end = rest.find("EXPLANATION:").unwrap_or(rest.len());
+        return CommandBlock {
+            text: rest[..end].trim().to_string(),
+            end: start + end,
+            complete: false,
+        };
+    }
+
+    let mut heredocs: Vec<String> = Vec::new();
+    // End of the line that opened the pending heredocs, to fall back to if
+    // their delimiter line never comes
+    let mut opened_at = start;
+    let mut end = start;
+    for line in rest.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\r', '\n']);
+        let terminated = line.ends_with('\n');
+
+        if let Some(delimiter) = heredocs.first() {
+            // The next candidate starts before the heredoc ends, so it wasn't one
+            if content.trim_start().starts_with("COMMAND:") {
+                return CommandBlock {
+                    text: text[start..opened_at].trim().to_string(),
+                    end: opened_at,
+                    complete: true,
+                };
+            }
+            // Lenient about indentation, which also covers `<<-` stripping tabs
+            if content.trim() == delimiter {
+                heredocs.remove(0);
+            }
+        } else if end > start
+            && ["COMMAND:", "EXPLANATION:"]
+                .iter()
+                .any(|marker| content.trim_start().starts_with(marker))
+        {
+            // A continuation never runs into the next part of the response
+            return CommandBlock {
+                text: text[start..end].trim().to_string(),
+                end,
+                complete: true,
+            };
+        } else {
+            heredocs.extend(heredoc_delimiters(content));
+            if !heredocs.is_empty() {
+                opened_at = end + line.len();
+            }
+        }
+        end += line.len();
+
+        let continues = !heredocs.is_empty() || content.trim_end().ends_with('\\');
+        if !continues {
+            return CommandBlock {
+                text: text[start..end].trim().to_string(),
+                end,
+                complete: terminated,
+            };
+        }
+    }
+
+    if !heredocs.is_empty() {
+        // The delimiter line never came: the `<<` only looked like a heredoc
+        return CommandBlock {
+            text: text[start..opened_at].trim().to_string(),
+            end: opened_at,
+            complete: false,
+        };
+    }
+    CommandBlock {
+        text: text[start..end].trim().to_string(),
+        end,
+        complete: false,
+    }
+}
+
+/// Extract a command fenced with triple backticks; `open` is just past the opening fence
+fn extract_fenced(text: &str, open: usize) -> CommandBlock {
+    let line_end = text[open..]
+        .find('\n')
+        .map(|i| open + i)
+        .unwrap_or(text.len());
+    // Inline fence: ```ls -la```
+    if let Some(close) = text[open..line_end].find("```") {
+        return CommandBlock {
+            text: text[open..open + close].trim().to_string(),
+            end: open + close + 3,
+            complete: true,
+        };
+    }
+
+    // Skip the language identifier, if any
+    let content_start = (line_end + 1).min(text.len());
+
+    match text[content_start..].find("```") {
+        Some(close) => CommandBlock {
+            text: text[content_start..content_start + close]
+                .trim()
+                .to_string(),
+            end: content_start + close + 3,
+            complete: true,
+        },
+        None => CommandBlock {
+            text: text[content_start..].trim().to_string(),
+            end: text.len(),
+            complete: false,
+        },
+    }
+}
+
+/// Delimiters of the heredocs opened on a line, in order. `<<` inside quotes
+/// or arithmetic (`$((x << n))`) is a literal or a shift, not a heredoc.
+fn heredoc_delimiters(line: &str) -> Vec<String> {
+    let mut delimiters = Vec::new();
+    let mut quote = None;
+    // Depth of `((` arithmetic, including `$((`
+    let mut arithmetic = 0;
+    let mut i = 0;
+
+    while i < line.len() {
+        let rest = &line[i..];
+        let c = rest.chars().next().expect("not at the end of the line");
+        if let Some(q) = quote {
+            if c == '\\' && q == '"' {
+                i += rest.chars().take(2).map(char::len_utf8).sum::<usize>();
+                continue;
+            }
+            if c == q {
+                quote = None;
+            }
+        } else if c == '\\' {
+            i += rest.chars().take(2).map(char::len_utf8).sum::<usize>();
+            continue;
+        } else if c == '\'' || c == '"' {
+            quote = Some(c);
+        } else if rest.starts_with("((") {
+            arithmetic += 1;
+            i += 2;
+            continue;
+        } else if arithmetic > 0 && rest.starts_with("))") {
+            arithmetic -= 1;
+            i += 2;
+            continue;
+        } else if arithmetic == 0 && rest.starts_with("<<") {
+            let after = &rest[2..];
+            // `<<<` is a here-string
+            if after.starts_with('<') {
+                i += 2 + after.len() - after.trim_start_matches('<').len();
+                continue;
+            }
+
+            let word = after.trim_start_matches('-').trim_start();
+            let unquoted = word.trim_start_matches(['\'', '"', '\\']);
+            let len = unquoted
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(unquoted.len());
+            let delimiter = &unquoted[..len];
+
+            // Requiring a leading letter skips shifts like `1<<4`
+            if delimiter.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+                delimiters.push(delimiter.to_string());
+            }
+            // Past the word, and the quote closing it, so it doesn't open one
+            let mut skip = 2 + after.len() - unquoted.len() + len;
+            if word.len() > unquoted.len() && line[i + skip..].starts_with(['\'', '"']) {
+                skip += 1;
+            }
+            i += skip;
+            continue;
+        }
+        i += c.len_utf8();
+    }
+
+    delimiters
+}
+
+/// Let the user choose a candidate with the arrow keys, rendered on stderr.
+/// Returns `None` if they cancel with Esc, q or Ctrl-C.
+pub fn pick_candidate(candidates: &[Candidate]) -> io::Result<Option<usize>> {
+    terminal::enable_raw_mode()?;
+    let result = run_picker(candidates);
+    let _ = terminal::disable_raw_mode();
+    result
+}
+
+fn run_picker(candidates: &[Candidate]) -> io::Result<Option<usize>> {
+    let mut stderr = io::stderr();
+    let width = match terminal::size() {
+        Ok((cols, _)) if cols > 0 => cols as usize,
+        _ => 80,
+    };
+    let mut selected = 0;
+    let mut drawn = 0;
+
+    let choice = loop {
+        // Redraw over the previous frame
+        if drawn > 1 {
+            queue!(stderr, cursor::MoveUp(drawn as u16 - 1))?;
+        }
+        queue!(
+            stderr,
+            cursor::MoveToColumn(0),
+            Clear(ClearType::FromCursorDown)
+        )?;
+        let lines = render_picker(candidates, selected, width);
+        write!(stderr, "{}", lines.join("\r\n"))?;
+        stderr.flush()?;
+        drawn = lines.len();
+
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => {
+                selected = selected.checked_sub(1).unwrap_or(candidates.len() - 1);
+            }
+            KeyCode::Down | KeyCode::Tab | KeyCode::Char('j') => {
+                selected = (selected + 1) % candidates.len();
+            }
+            KeyCode::Enter => break Some(selected),
+            KeyCode::Char(c @ '1'..='9') if (c as usize - '1' as usize) < candidates.len() => {
+                break Some(c as usize - '1' as usize);
+            }
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => break None,
+            KeyCode::Esc | KeyCode::Char('q') => break None,
+            _ => {}
+        }
+    };
+
+    // Leave no trace of the picker
+    if drawn > 1 {
+        queue!(stderr, cursor::MoveUp(drawn as u16 - 1))?;
+    }
+    queue!(
+        stderr,
+        cursor::MoveToColumn(0),
+        Clear(ClearType::FromCursorDown)
+    )?;
+    stderr.flush()?;
+    Ok(choice)
+}
+
+/// Lines of the picker: a hint, then one line per candidate, with the
+/// selected one's explanation below it. Lines are cut to fit `width` so
+/// none of them wrap.
+fn render_picker(candidates: &[Candidate], selected: usize, width: usize) -> Vec<String> {
+    let fit = |text: &str, indent: usize| -> String {
+        let max = width.saturating_sub(indent + 1);
+        let mut lines = text.lines();
+        let first = lines.next().unwrap_or("");
+        if first.chars().count() > max || lines.next().is_some() {
+            let cut: String = first.chars().take(max.saturating_sub(2)).collect();
+            format!("{} …", cut)
+        } else {
+            first.to_string()
+        }
+    };
+
+    let mut lines = vec![format!(
+        "{}Pick a command (↑/↓ or 1-{}, Enter to choose, Esc to cancel){}",
+        DIM,
+        candidates.len().min(9),
+        RESET
+    )];
+    for (i, candidate) in candidates.iter().enumerate() {
+        let command = fit(&candidate.command, 5);
+        if i == selected {
+            lines.push(format!("{}❯ {}. {}{}", BOLD, i + 1, command, RESET));
+            if let Some(explanation) = &candidate.explanation {
+                lines.push(format!("{}     {}{}", DIM, fit(explanation, 5), RESET));
+            }
+        } else {
+            lines.push(format!("  {}. {}", i + 1, command));
+        }
+    }
+    lines
+}
+
+/// A structured explanation of an existing command, as produced by `halp explain`
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Breakdown {
+    pub summary: Option<String>,
+    pub stages: Vec<Stage>,
+}
+
+/// One pipeline stage or chained command
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Stage {
+    /// The stage's text, empty if the model listed parts without a stage
+    pub command: String,
+    pub parts: Vec<Part>,
+}
+
+/// A program name, flag, argument or operator within a stage
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Part {
+    pub token: String,
+    pub description: String,
+}
+
+/// Widest token column before descriptions stop being aligned
+const MAX_TOKEN_WIDTH: usize = 24;
+
+/// Parse `SUMMARY:`, `STAGE:` and `PART: <token> :: <description>` lines.
+/// Returns `None` if the response contains none of them.
+pub fn parse_breakdown(response: &str) -> Option<Breakdown> {
+    let mut summary = None;
+    let mut stages: Vec<Stage> = Vec::new();
+
+    for line in response.lines() {
+        let line = line.trim();
+        let line = line.strip_prefix("- ").unwrap_or(line);
+
+        if let Some(text) = line.strip_prefix("SUMMARY:") {
+            let text = text.trim();
+            if !text.is_empty() {
+                summary = Some(text.to_string());
+            }
+        } else if let Some(text) = line.strip_prefix("STAGE:") {
+            stages.push(Stage {
+                command: text.trim().to_string(),
+                parts: Vec::new(),
+            });
+        } else if let Some(text) = line.strip_prefix("PART:") {
+            let (token, description) = text.split_once(" :: ").unwrap_or((text, ""));
+            let part = Part {
+                token: token.trim().to_string(),
+                description: description.trim().to_string(),
+            };
+            if part.token.is_empty() {
+                continue;
+            }
+            match stages.last_mut() {
+                Some(stage) => stage.parts.push(part),
+                None => stages.push(Stage {
+                    command: String::new(),
+                    parts: vec![part],
+                }),
+            }
+        }
+    }
+
+    if summary.is_none() && stages.is_empty() {
+        return None;
+    }
+    Some(Breakdown { summary, stages })
+}
+
+impl fmt::Display for Breakdown {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut first = true;
+        if let Some(summary) = &self.summary {
+            write!(f, "{}", summary)?;
+            first = false;
+        }
+
+        for stage in &self.stages {
+            if !first {
+                writeln!(f)?;
+                writeln!(f)?;
+            }
+            first = false;
+
+            let indent = if stage.command.is_empty() {
+                ""
+            } else {
+                write!(f, "{}", stage.command)?;
+                if !stage.parts.is_empty() {
+                    writeln!(f)?;
+                }
+                "  "
+            };
+
+            let width = stage
+                .parts
+                .iter()
+                .map(|part| part.token.chars().count())
+                .filter(|&len| len <= MAX_TOKEN_WIDTH)
+                .max()
+                .unwrap_or(0);
+            for (i, part) in stage.parts.iter().enumerate() {
+                if i > 0 {
+                    writeln!(f)?;
+                }
+                write!(
+                    f,
+                    "{}{:width$}  {}",
+                    indent,
+                    part.token,
+                    part.description,
+                    width = width
+                )?;
+            }
+        }
+        Ok(())
     }
 }
 
@@ -104,6 +607,8 @@ pub fn parse_response(response: &str) -> ParsedResponse {
 pub struct StderrStreamer {
     started: bool,
     spinner: Option<Spinner>,
+    /// Whether to echo the response, or only show the spinner until it's done
+    echo: bool,
 }
 
 impl StderrStreamer {
@@ -111,6 +616,16 @@ impl StderrStreamer {
         Self {
             started: false,
             spinner,
+            echo: true,
+        }
+    }
+
+    /// Show the spinner until `finish` without echoing the response
+    pub fn spinner_only(spinner: Spinner) -> Self {
+        Self {
+            started: false,
+            spinner: Some(spinner),
+            echo: false,
         }
     }
 
@@ -129,6 +644,9 @@ impl StderrStreamer {
 
 impl Write for StderrStreamer {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.echo {
+            return Ok(buf.len());
+        }
         if !self.started {
             self.started = true;
             if let Some(spinner) = self.spinner.take() {
@@ -168,10 +686,10 @@ mod tests {
         let response = "COMMAND: ls -la\nEXPLANATION: Lists all files including hidden ones";
         let parsed = parse_response(response);
 
-        assert_eq!(parsed.command, Some("ls -la".to_string()));
+        assert_eq!(parsed.command(), Some("ls -la"));
         assert_eq!(
-            parsed.explanation,
-            Some("Lists all files including hidden ones".to_string())
+            parsed.explanation(),
+            Some("Lists all files including hidden ones")
         );
     }
 
@@ -180,8 +698,8 @@ mod tests {
         let response = "COMMAND: pwd";
         let parsed = parse_response(response);
 
-        assert_eq!(parsed.command, Some("pwd".to_string()));
-        assert_eq!(parsed.explanation, None);
+        assert_eq!(parsed.command(), Some("pwd"));
+        assert_eq!(parsed.explanation(), None);
     }
 
     #[test]
@@ -191,13 +709,10 @@ mod tests {
 
         // Falls back to first non-empty line
         assert_eq!(
-            parsed.command,
-            Some("EXPLANATION: This explains something".to_string())
-        );
-        assert_eq!(
-            parsed.explanation,
-            Some("This explains something".to_string())
+            parsed.command(),
+            Some("EXPLANATION: This explains something")
         );
+        assert_eq!(parsed.explanation(), Some("This explains something"));
     }
 
     #[test]
@@ -205,8 +720,8 @@ mod tests {
         let response = "Here's the command:\n```bash\ngrep -r \"pattern\" .\n```";
         let parsed = parse_response(response);
 
-        assert_eq!(parsed.command, Some("grep -r \"pattern\" .".to_string()));
-        assert_eq!(parsed.explanation, None);
+        assert_eq!(parsed.command(), Some("grep -r \"pattern\" ."));
+        assert_eq!(parsed.explanation(), None);
     }
 
     #[test]
@@ -214,7 +729,7 @@ mod tests {
         let response = "```\necho hello\n```";
         let parsed = parse_response(response);
 
-        assert_eq!(parsed.command, Some("echo hello".to_string()));
+        assert_eq!(parsed.command(), Some("echo hello"));
     }
 
     #[test]
@@ -222,7 +737,7 @@ mod tests {
         let response = "git status\nThis shows the status";
         let parsed = parse_response(response);
 
-        assert_eq!(parsed.command, Some("git status".to_string()));
+        assert_eq!(parsed.command(), Some("git status"));
     }
 
     #[test]
@@ -230,8 +745,8 @@ mod tests {
         let response = "";
         let parsed = parse_response(response);
 
-        assert_eq!(parsed.command, None);
-        assert_eq!(parsed.explanation, None);
+        assert_eq!(parsed.command(), None);
+        assert_eq!(parsed.explanation(), None);
     }
 
     #[test]
@@ -239,8 +754,8 @@ mod tests {
         let response = "   \n\n   ";
         let parsed = parse_response(response);
 
-        assert_eq!(parsed.command, None);
-        assert_eq!(parsed.explanation, None);
+        assert_eq!(parsed.command(), None);
+        assert_eq!(parsed.explanation(), None);
     }
 
     #[test]
@@ -248,9 +763,195 @@ mod tests {
         let response = "COMMAND: docker run -it \\\n  --name test \\\n  ubuntu\nEXPLANATION: Runs ubuntu";
         let parsed = parse_response(response);
 
-        // Command ends at first newline per current implementation
-        assert_eq!(parsed.command, Some("docker run -it \\".to_string()));
-        assert_eq!(parsed.explanation, Some("Runs ubuntu".to_string()));
+        // Backslash continuations keep the command going
+        assert_eq!(
+            parsed.command(),
+            Some("docker run -it \\\n  --name test \\\n  ubuntu")
+        );
+        assert_eq!(parsed.explanation(), Some("Runs ubuntu"));
+    }
+
+    #[test]
+    fn test_parse_heredoc_command() {
+        let response = "COMMAND: cat <<'EOF' > config.yaml\nname: test\nEXPLANATION: not this\nEOF\nEXPLANATION: Writes config.yaml";
+        let parsed = parse_response(response);
+
+        assert_eq!(
+            parsed.command(),
+            Some("cat <<'EOF' > config.yaml\nname: test\nEXPLANATION: not this\nEOF")
+        );
+        assert_eq!(parsed.explanation(), Some("Writes config.yaml"));
+    }
+
+    #[test]
+    fn test_parse_heredoc_strip_tabs() {
+        let response =
+            "COMMAND: kubectl apply -f - <<-YAML\n\tkind: Pod\n\tYAML\nEXPLANATION: Applies a pod";
+        let parsed = parse_response(response);
+
+        assert_eq!(
+            parsed.command(),
+            Some("kubectl apply -f - <<-YAML\n\tkind: Pod\n\tYAML")
+        );
+    }
+
+    #[test]
+    fn test_parse_here_string_and_shift_are_not_heredocs() {
+        let parsed = parse_response("COMMAND: grep foo <<< \"$x\"\nEXPLANATION: Greps");
+        assert_eq!(parsed.command(), Some("grep foo <<< \"$x\""));
+
+        let parsed = parse_response("COMMAND: echo $((1 << 4))\nEXPLANATION: Prints 16");
+        assert_eq!(parsed.command(), Some("echo $((1 << 4))"));
+    }
+
+    #[test]
+    fn test_parse_quoted_and_arithmetic_shifts_are_not_heredocs() {
+        let parsed = parse_response("COMMAND: echo $((x << n))\nEXPLANATION: Shifts x");
+        assert_eq!(parsed.command(), Some("echo $((x << n))"));
+        assert_eq!(parsed.explanation(), Some("Shifts x"));
+
+        let parsed = parse_response("COMMAND: (( mask = 1<<bits ))\nEXPLANATION: Sets a bit");
+        assert_eq!(parsed.command(), Some("(( mask = 1<<bits ))"));
+
+        let parsed =
+            parse_response("COMMAND: awk '{ print 1<<bits }' f\nEXPLANATION: Powers of two");
+        assert_eq!(parsed.command(), Some("awk '{ print 1<<bits }' f"));
+
+        let parsed = parse_response("COMMAND: echo \"use <<EOF here\"\nEXPLANATION: Prints it");
+        assert_eq!(parsed.command(), Some("echo \"use <<EOF here\""));
+    }
+
+    #[test]
+    fn test_parse_unterminated_heredoc_falls_back_to_line() {
+        let parsed = parse_response("COMMAND: cat <<EOF > notes.txt\nEXPLANATION: Writes notes");
+        assert_eq!(parsed.command(), Some("cat <<EOF > notes.txt"));
+        assert_eq!(parsed.explanation(), Some("Writes notes"));
+
+        let parsed =
+            parse_response("COMMAND: cat <<END\nEXPLANATION: One\n\nCOMMAND: ls\nEXPLANATION: Two");
+        assert_eq!(parsed.command(), Some("cat <<END"));
+        assert_eq!(parsed.explanation(), Some("One"));
+        assert_eq!(parsed.candidates[1].command, "ls");
+
+        // Quoted delimiters are still heredocs
+        let parsed = parse_response("COMMAND: cat <<\"EOF\"\n$HOME\nEOF\nEXPLANATION: Literal");
+        assert_eq!(parsed.command(), Some("cat <<\"EOF\"\n$HOME\nEOF"));
+    }
+
+    #[test]
+    fn test_parse_continuation_stops_at_explanation() {
+        let parsed = parse_response("COMMAND: make \\\nEXPLANATION: Builds");
+        assert_eq!(parsed.command(), Some("make \\"));
+        assert_eq!(parsed.explanation(), Some("Builds"));
+    }
+
+    #[test]
+    fn test_parse_fenced_command() {
+        let response = "COMMAND:\n```bash\nfor f in *.log; do\n  gzip \"$f\"\ndone\n```\nEXPLANATION: Compresses logs";
+        let parsed = parse_response(response);
+
+        assert_eq!(
+            parsed.command(),
+            Some("for f in *.log; do\n  gzip \"$f\"\ndone")
+        );
+        assert_eq!(parsed.explanation(), Some("Compresses logs"));
+
+        let parsed = parse_response("COMMAND: ```ls -la```\nEXPLANATION: Lists");
+        assert_eq!(parsed.command(), Some("ls -la"));
+    }
+
+    #[test]
+    fn test_parse_command_on_next_line() {
+        let parsed = parse_response("COMMAND:\nls -la\nEXPLANATION: Lists files");
+        assert_eq!(parsed.command(), Some("ls -la"));
+    }
+
+    #[test]
+    fn test_parse_alternatives() {
+        let response = "COMMAND: find . -size +100M\nEXPLANATION: GNU find\n\nCOMMAND: find . -size +204800\nEXPLANATION: POSIX block count\nCOMMAND: du -a | sort -n\n";
+        let parsed = parse_response(response);
+
+        assert_eq!(
+            parsed.candidates,
+            vec![
+                Candidate {
+                    command: "find . -size +100M".to_string(),
+                    explanation: Some("GNU find".to_string()),
+                },
+                Candidate {
+                    command: "find . -size +204800".to_string(),
+                    explanation: Some("POSIX block count".to_string()),
+                },
+                Candidate {
+                    command: "du -a | sort -n".to_string(),
+                    explanation: None,
+                },
+            ]
+        );
+        assert_eq!(parsed.command(), Some("find . -size +100M"));
+    }
+
+    #[test]
+    fn test_parse_command_prefix_in_prose() {
+        let response = "COMMAND: ls -la\nEXPLANATION: Lists\n\nNote: COMMAND: is the prefix";
+        let parsed = parse_response(response);
+
+        assert_eq!(parsed.candidates.len(), 1);
+        assert_eq!(parsed.command(), Some("ls -la"));
+        assert_eq!(
+            parsed.explanation(),
+            Some("Lists\n\nNote: COMMAND: is the prefix")
+        );
+
+        let parsed = parse_response("COMMAND: ls\nEXPLANATION: Lists\n  COMMAND: ls -a\n");
+        assert_eq!(parsed.candidates.len(), 2);
+        assert_eq!(parsed.candidates[1].command, "ls -a");
+    }
+
+    #[test]
+    fn test_parse_explanation_before_command() {
+        let parsed = parse_response("EXPLANATION: Lists files\nCOMMAND: ls\n");
+        assert_eq!(parsed.command(), Some("ls"));
+        assert_eq!(parsed.explanation(), Some("Lists files"));
+    }
+
+    #[test]
+    fn test_render_picker() {
+        let candidates = vec![
+            Candidate {
+                command: "find . -size +100M".to_string(),
+                explanation: Some("GNU find".to_string()),
+            },
+            Candidate {
+                command: "docker run \\\n  ubuntu".to_string(),
+                explanation: Some("Multi-line".to_string()),
+            },
+        ];
+
+        let lines = render_picker(&candidates, 1, 80);
+
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[1], "  1. find . -size +100M");
+        assert!(lines[2].contains("❯ 2. docker run \\ …"));
+        assert!(lines[3].contains("Multi-line"));
+
+        // Long commands are cut to the terminal width
+        let lines = render_picker(&candidates, 1, 12);
+        assert_eq!(lines[1], "  1. find …");
+    }
+
+    #[test]
+    fn test_has_complete_command() {
+        assert!(!has_complete_command("COMMAND: ls -l"));
+        assert!(!has_complete_command("COMMAND: \n"));
+        assert!(!has_complete_command("Sure, here you go\n"));
+        assert!(has_complete_command("COMMAND: ls -l\nEXPL"));
+        assert!(!has_complete_command("COMMAND: docker run \\\n  ubuntu"));
+        assert!(has_complete_command("COMMAND: docker run \\\n  ubuntu\n"));
+        assert!(!has_complete_command("COMMAND: cat <<EOF\nhello\n"));
+        assert!(has_complete_command("COMMAND: cat <<EOF\nhello\nEOF\n"));
+        assert!(!has_complete_command("COMMAND: ```\nls\n"));
+        assert!(has_complete_command("COMMAND: ```\nls\n```"));
     }
 
     #[test]
@@ -258,8 +959,8 @@ mod tests {
         let response = "Some text\nCOMMAND: ls\nMore text\nEXPLANATION: Lists files";
         let parsed = parse_response(response);
 
-        assert_eq!(parsed.command, Some("ls".to_string()));
-        assert_eq!(parsed.explanation, Some("Lists files".to_string()));
+        assert_eq!(parsed.command(), Some("ls"));
+        assert_eq!(parsed.explanation(), Some("Lists files"));
     }
 
     #[test]
@@ -267,8 +968,63 @@ mod tests {
         let response = "COMMAND:    echo hello   \nEXPLANATION:   Says hello   ";
         let parsed = parse_response(response);
 
-        assert_eq!(parsed.command, Some("echo hello".to_string()));
-        assert_eq!(parsed.explanation, Some("Says hello".to_string()));
+        assert_eq!(parsed.command(), Some("echo hello"));
+        assert_eq!(parsed.explanation(), Some("Says hello"));
+    }
+
+    #[test]
+    fn test_parse_breakdown() {
+        let response = "SUMMARY: Counts lines in Rust files\n\
+STAGE: find . -name \"*.rs\"\n\
+PART: find :: Search for files\n\
+PART: -name \"*.rs\" :: Match names ending in .rs\n\
+STAGE: xargs wc -l\n\
+- PART: xargs :: Pass the file names as arguments\n\
+PART: wc -l :: Count lines\n";
+        let breakdown = parse_breakdown(response).unwrap();
+
+        assert_eq!(
+            breakdown.summary,
+            Some("Counts lines in Rust files".to_string())
+        );
+        assert_eq!(breakdown.stages.len(), 2);
+        assert_eq!(breakdown.stages[0].command, "find . -name \"*.rs\"");
+        assert_eq!(
+            breakdown.stages[0].parts[1],
+            Part {
+                token: "-name \"*.rs\"".to_string(),
+                description: "Match names ending in .rs".to_string(),
+            }
+        );
+        assert_eq!(breakdown.stages[1].parts[0].token, "xargs");
+    }
+
+    #[test]
+    fn test_parse_breakdown_without_stage() {
+        let breakdown = parse_breakdown("PART: ls :: List files\nPART: -a").unwrap();
+
+        assert_eq!(breakdown.summary, None);
+        assert_eq!(breakdown.stages.len(), 1);
+        assert_eq!(breakdown.stages[0].command, "");
+        assert_eq!(breakdown.stages[0].parts[1].description, "");
+    }
+
+    #[test]
+    fn test_parse_breakdown_unstructured() {
+        assert_eq!(parse_breakdown("It lists files."), None);
+    }
+
+    #[test]
+    fn test_display_breakdown() {
+        let breakdown = parse_breakdown(
+            "SUMMARY: Lists files\nSTAGE: ls -la\nPART: ls :: List directory contents\nPART: -la :: Long format, including hidden files",
+        )
+        .unwrap();
+
+        assert_eq!(
+            breakdown.to_string(),
+            "Lists files\n\nls -la\n  ls   List directory contents\n  -la  Long format, including hidden files"
+        );
     }
 
     #[test]
@@ -0,0 +1,416 @@
+use crate::config::Config;
+use crate::error::HalpError;
+use crate::output::ParsedResponse;
+use crate::safety::simple_commands;
+use globset::{Glob, GlobMatcher};
+use regex::Regex;
+use serde::Deserialize;
+use std::cell::OnceCell;
+use std::fmt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::{env, fs};
+
+/// What happens to a command that matches a rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    /// Let the command through, skipping any later rules
+    Allow,
+    /// Never output the command
+    Deny,
+    /// Output the command, marked as needing confirmation before it's run
+    Confirm,
+}
+
+/// A rule as written in `policy.toml`
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleEntry {
+    action: Action,
+    regex: Option<String>,
+    glob: Option<String>,
+    kube_context: Option<String>,
+    message: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default, rename = "rule")]
+    rules: Vec<RuleEntry>,
+}
+
+#[derive(Debug)]
+enum Pattern {
+    /// Searched for anywhere in the command line
+    Regex(Regex),
+    /// Matched against the whole command line, or any single command in it
+    Glob(GlobMatcher),
+}
+
+/// One rule of a [`Policy`]
+#[derive(Debug)]
+pub struct Rule {
+    pub action: Action,
+    pattern: Pattern,
+    /// Only applies when the kubectl context matches
+    kube_context: Option<GlobMatcher>,
+    /// Why the rule exists, shown when it matches
+    pub message: Option<String>,
+}
+
+impl Rule {
+    fn from_entry(entry: RuleEntry) -> Result<Self, HalpError> {
+        let pattern = match (entry.regex, entry.glob) {
+            (Some(regex), None) => Pattern::Regex(
+                Regex::new(&regex)
+                    .map_err(|e| HalpError::Config(format!("Invalid policy regex: {}", e)))?,
+            ),
+            (None, Some(glob)) => Pattern::Glob(compile_glob(&glob)?),
+            _ => {
+                return Err(HalpError::Config(
+                    "Each policy rule needs exactly one of `regex` or `glob`".to_string(),
+                ))
+            }
+        };
+        let kube_context = entry
+            .kube_context
+            .as_deref()
+            .map(compile_glob)
+            .transpose()?;
+
+        Ok(Rule {
+            action: entry.action,
+            pattern,
+            kube_context,
+            message: entry.message,
+        })
+    }
+
+    /// What the rule matches, e.g. "`kubectl delete *` in kubectl contexts matching `prod*`"
+    pub fn describe(&self) -> String {
+        let pattern = match &self.pattern {
+            Pattern::Regex(regex) => format!("/{}/", regex.as_str()),
+            Pattern::Glob(glob) => format!("`{}`", glob.glob()),
+        };
+        match &self.kube_context {
+            Some(context) => format!(
+                "{} in kubectl contexts matching `{}`",
+                pattern,
+                context.glob()
+            ),
+            None => pattern,
+        }
+    }
+
+    fn matches(&self, command: &str, current_context: &dyn Fn() -> Option<String>) -> bool {
+        let commands = simple_commands(command);
+        let matched = match &self.pattern {
+            Pattern::Regex(regex) => regex.is_match(command),
+            Pattern::Glob(glob) => {
+                glob.is_match(command.trim())
+                    || commands.iter().any(|words| glob.is_match(words.join(" ")))
+            }
+        };
+        if !matched {
+            return false;
+        }
+
+        match &self.kube_context {
+            // A `--context` in the command wins over kubectl's current context. When
+            // neither is known the rule doesn't apply.
+            Some(pattern) => context_flag(&commands)
+                .or_else(current_context)
+                .is_some_and(|context| pattern.is_match(context)),
+            None => true,
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.message {
+            Some(message) => write!(f, "{}", message),
+            None => write!(f, "matches {}", self.describe()),
+        }
+    }
+}
+
+fn compile_glob(glob: &str) -> Result<GlobMatcher, HalpError> {
+    Glob::new(glob)
+        .map(|g| g.compile_matcher())
+        .map_err(|e| HalpError::Config(format!("Invalid policy glob: {}", e)))
+}
+
+/// The value of `--context` passed to kubectl in the command, if any
+fn context_flag(commands: &[Vec<String>]) -> Option<String> {
+    commands
+        .iter()
+        .filter(|words| words.first().is_some_and(|p| p.ends_with("kubectl")))
+        .flat_map(|words| words.windows(2))
+        .find_map(|pair| match pair[0].strip_prefix("--context") {
+            Some("") => Some(pair[1].clone()),
+            Some(value) => value.strip_prefix('=').map(str::to_string),
+            None => None,
+        })
+}
+
+/// kubectl's current context, if kubectl is installed and has one
+fn current_kube_context() -> Option<String> {
+    let output = Command::new("kubectl")
+        .args(["config", "current-context"])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let context = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !context.is_empty()).then_some(context)
+}
+
+/// Rules over the generated command, from `policy.toml`. Rules are checked
+/// in order and the first one that matches decides.
+#[derive(Debug, Default)]
+pub struct Policy {
+    pub rules: Vec<Rule>,
+}
+
+impl Policy {
+    /// Load from `$HALP_POLICY`, or `policy.toml` next to `config.toml`.
+    /// A missing file is an empty policy; an invalid one is an error, so a
+    /// broken policy never silently allows everything.
+    pub fn load() -> Result<Self, HalpError> {
+        let Some(path) = Self::path() else {
+            return Ok(Policy::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(content) => Self::from_toml(&content).map_err(|e| match e {
+                HalpError::Config(message) => {
+                    HalpError::Config(format!("{}: {}", path.display(), message))
+                }
+                e => e,
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Policy::default()),
+            Err(e) => Err(HalpError::Config(format!(
+                "Failed to read {}: {}",
+                path.display(),
+                e
+            ))),
+        }
+    }
+
+    fn path() -> Option<PathBuf> {
+        env::var("HALP_POLICY")
+            .map(PathBuf::from)
+            .ok()
+            .or_else(|| Some(Config::config_dir()?.join("policy.toml")))
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, HalpError> {
+        let file: PolicyFile = toml::from_str(content)
+            .map_err(|e| HalpError::Config(format!("Invalid policy: {}", e)))?;
+        let rules = file
+            .rules
+            .into_iter()
+            .map(Rule::from_entry)
+            .collect::<Result<_, _>>()?;
+        Ok(Policy { rules })
+    }
+
+    /// The first rule matching the command, if any
+    pub fn check(&self, command: &str) -> Option<&Rule> {
+        // Only ask kubectl when a rule needs it, and at most once
+        let context = OnceCell::new();
+        self.check_with(command, &|| {
+            context.get_or_init(current_kube_context).clone()
+        })
+    }
+
+    /// Drop the candidates the policy denies, returning a message for each
+    pub fn remove_denied(&self, parsed: &mut ParsedResponse) -> Vec<String> {
+        let mut denied = Vec::new();
+        parsed
+            .candidates
+            .retain(|candidate| match self.check(&candidate.command) {
+                Some(rule) if rule.action == Action::Deny => {
+                    denied.push(format!(
+                        "Blocked by policy: {} ({})",
+                        rule, candidate.command
+                    ));
+                    false
+                }
+                _ => true,
+            });
+        denied
+    }
+
+    /// A note if the policy wants the command confirmed before it's run
+    pub fn confirmation(&self, command: &str) -> Option<String> {
+        self.check(command)
+            .filter(|rule| rule.action == Action::Confirm)
+            .map(|rule| format!("Confirm before running: {}", rule))
+    }
+
+    fn check_with(
+        &self,
+        command: &str,
+        current_context: &dyn Fn() -> Option<String>,
+    ) -> Option<&Rule> {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(command, current_context))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: &str = r#"
+[[rule]]
+action = "allow"
+glob = "kubectl delete pod debug-*"
+
+[[rule]]
+action = "deny"
+glob = "kubectl delete *"
+kube_context = "prod*"
+message = "Production clusters are managed through the deploy pipeline"
+
+[[rule]]
+action = "confirm"
+regex = "^terraform (apply|destroy)"
+"#;
+
+    fn action(policy: &Policy, command: &str, context: Option<&str>) -> Option<Action> {
+        let context = context.map(str::to_string);
+        policy
+            .check_with(command, &|| context.clone())
+            .map(|rule| rule.action)
+    }
+
+    #[test]
+    fn test_kube_context() {
+        let policy = Policy::from_toml(POLICY).unwrap();
+
+        assert_eq!(
+            action(&policy, "kubectl delete pod web-1", Some("prod-east")),
+            Some(Action::Deny)
+        );
+        assert_eq!(
+            action(&policy, "kubectl delete pod web-1", Some("staging")),
+            None
+        );
+        assert_eq!(action(&policy, "kubectl delete pod web-1", None), None);
+        assert_eq!(
+            action(
+                &policy,
+                "kubectl delete --context prod-west pod web-1",
+                Some("staging")
+            ),
+            Some(Action::Deny)
+        );
+        assert_eq!(
+            action(
+                &policy,
+                "kubectl delete --context=staging pod web-1",
+                Some("prod-east")
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_glob_matches_any_command_in_line() {
+        let policy = Policy::from_toml(POLICY).unwrap();
+
+        assert_eq!(
+            action(
+                &policy,
+                "kubectl get pods && sudo kubectl delete pod web-1",
+                Some("prod")
+            ),
+            Some(Action::Deny)
+        );
+        assert_eq!(action(&policy, "kubectl get pods", Some("prod")), None);
+    }
+
+    #[test]
+    fn test_first_match_wins() {
+        let policy = Policy::from_toml(POLICY).unwrap();
+
+        assert_eq!(
+            action(&policy, "kubectl delete pod debug-123", Some("prod")),
+            Some(Action::Allow)
+        );
+        assert_eq!(
+            action(&policy, "terraform apply -auto-approve", None),
+            Some(Action::Confirm)
+        );
+        assert_eq!(action(&policy, "terraform plan", None), None);
+    }
+
+    #[test]
+    fn test_describe() {
+        let policy = Policy::from_toml(POLICY).unwrap();
+
+        assert_eq!(
+            policy.rules[1].describe(),
+            "`kubectl delete *` in kubectl contexts matching `prod*`"
+        );
+        assert_eq!(
+            policy.rules[1].to_string(),
+            "Production clusters are managed through the deploy pipeline"
+        );
+        assert_eq!(
+            policy.rules[2].to_string(),
+            "matches /^terraform (apply|destroy)/"
+        );
+    }
+
+    #[test]
+    fn test_remove_denied() {
+        let policy = Policy::from_toml(
+            "[[rule]]\naction = \"deny\"\nglob = \"rm -rf *\"\nmessage = \"Use trash\"",
+        )
+        .unwrap();
+        let mut parsed = crate::parse_response(
+            "COMMAND: rm -rf build\nEXPLANATION: Delete it\n\nCOMMAND: trash build\nEXPLANATION: Recoverable",
+        );
+
+        let denied = policy.remove_denied(&mut parsed);
+
+        assert_eq!(denied, ["Blocked by policy: Use trash (rm -rf build)"]);
+        assert_eq!(parsed.command(), Some("trash build"));
+        assert_eq!(parsed.candidates.len(), 1);
+    }
+
+    #[test]
+    fn test_confirmation() {
+        let policy = Policy::from_toml(POLICY).unwrap();
+
+        assert_eq!(
+            policy.confirmation("terraform destroy").as_deref(),
+            Some("Confirm before running: matches /^terraform (apply|destroy)/")
+        );
+        assert_eq!(policy.confirmation("terraform plan"), None);
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        let both = "[[rule]]\naction = \"deny\"\nregex = \"x\"\nglob = \"x\"";
+        assert!(matches!(Policy::from_toml(both), Err(HalpError::Config(_))));
+
+        let bad_regex = "[[rule]]\naction = \"deny\"\nregex = \"(\"";
+        assert!(matches!(
+            Policy::from_toml(bad_regex),
+            Err(HalpError::Config(_))
+        ));
+
+        let unknown_action = "[[rule]]\naction = \"warn\"\nglob = \"x\"";
+        assert!(matches!(
+            Policy::from_toml(unknown_action),
+            Err(HalpError::Config(_))
+        ));
+    }
+}
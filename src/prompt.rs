@@ -1,4 +1,81 @@
+use crate::policy::{Action, Policy};
 use std::env;
+use std::io::{self, Read};
+
+/// Content piped into halp on stdin, to be sent along with the query
+#[derive(Debug, Clone, PartialEq)]
+pub struct StdinContext {
+    pub content: String,
+    /// The input was longer than the size limit and has been cut off
+    pub truncated: bool,
+}
+
+/// Read up to `limit` bytes of context. Returns `None` for empty input.
+pub fn read_context(reader: impl Read, limit: usize) -> io::Result<Option<StdinContext>> {
+    let mut bytes = Vec::new();
+    // Read one byte past the limit to find out whether there was more
+    reader.take(limit as u64 + 1).read_to_end(&mut bytes)?;
+
+    let truncated = bytes.len() > limit;
+    bytes.truncate(limit);
+
+    let content = String::from_utf8_lossy(&bytes).into_owned();
+    if content.trim().is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(StdinContext { content, truncated }))
+}
+
+/// Combine the user's request with any piped-in context
+pub fn build_user_prompt(query: &str, context: Option<&StdinContext>) -> String {
+    match context {
+        Some(context) => format!(
+            "The following input was piped into halp. Use it to tailor the command (exact names, paths, formats){}:\n<stdin>\n{}\n</stdin>\n\nRequest: {}",
+            if context.truncated {
+                "; it was truncated"
+            } else {
+                ""
+            },
+            context.content.trim_end(),
+            query
+        ),
+        None => query.to_string(),
+    }
+}
+
+/// Ask for a corrected version of a command that failed
+pub fn build_fix_prompt(
+    command: &str,
+    status: Option<i32>,
+    stderr: Option<&StdinContext>,
+) -> String {
+    let mut prompt = match status {
+        Some(status) => format!("This command failed with exit status {}:\n", status),
+        None => "This command failed:\n".to_string(),
+    };
+    prompt.push_str(&format!("<command>\n{}\n</command>\n", command.trim()));
+
+    if let Some(stderr) = stderr {
+        prompt.push_str(&format!(
+            "\nIts error output{}:\n<stderr>\n{}\n</stderr>\n",
+            if stderr.truncated { " (truncated)" } else { "" },
+            stderr.content.trim_end()
+        ));
+    }
+
+    prompt.push_str(
+        "\nRequest: Give the corrected command that does what was intended. \
+Fix typos, wrong flags or arguments, missing permissions and the like. \
+If the command itself is fine, give a command that addresses the cause of the failure, \
+and say what was wrong in the explanation.",
+    );
+    prompt
+}
+
+/// The rule in the default system prompt that asks for a single command
+const ONE_COMMAND_RULE: &str =
+    "- Output exactly one command (use && or ; for multi-step operations)";
 
 pub fn build_system_prompt(custom_template: Option<&str>) -> String {
     let os = get_os();
@@ -23,17 +100,85 @@ Context:
 - Working directory: {}
 
 Rules:
-- Output exactly one command (use && or ; for multi-step operations)
+{}
 - The command must be valid for the specified OS and shell
 - Prefer common, portable commands when possible
 - Keep explanation to one concise line
 - Never include dangerous commands (rm -rf /, etc) without explicit confirmation flags
 - If the request is ambiguous, make a reasonable assumption and note it in the explanation"#,
-            os, shell, cwd
+            os, shell, cwd, ONE_COMMAND_RULE
         ),
     }
 }
 
+/// The system prompt changed to ask for several ranked commands instead of
+/// one. The default prompt's one-command rule is relaxed to apply to each of
+/// them, so the instructions don't contradict each other.
+pub fn build_alternatives_prompt(system_prompt: &str, count: usize) -> String {
+    format!(
+        "{}\n\nInstead of one command, give {} alternative commands, ranked best first. \
+Repeat the COMMAND: and EXPLANATION: lines for each one. \
+Make them genuinely different, e.g. other tools, or GNU versus BSD flags.",
+        system_prompt.replace(
+            ONE_COMMAND_RULE,
+            "- Make each alternative a single command (use && or ; for multi-step operations)"
+        ),
+        count
+    )
+}
+
+/// Added to the system prompt so the model follows the user's policy rules
+pub fn build_policy_prompt(policy: &Policy) -> String {
+    let mut constraints = String::new();
+    for rule in &policy.rules {
+        let line = match rule.action {
+            Action::Allow => continue,
+            Action::Deny => format!("- Never give a command matching {}", rule.describe()),
+            Action::Confirm => format!(
+                "- Commands matching {} need confirmation; say so in the explanation",
+                rule.describe()
+            ),
+        };
+        constraints.push_str(&line);
+        if let Some(message) = &rule.message {
+            constraints.push_str(&format!(" ({})", message));
+        }
+        constraints.push('\n');
+    }
+
+    if constraints.is_empty() {
+        return String::new();
+    }
+    format!(
+        "\n\nPolicy set by the user's organization, which always applies:\n{}",
+        constraints.trim_end()
+    )
+}
+
+/// System prompt for `halp explain`, which breaks down an existing command
+pub fn build_explain_system_prompt() -> String {
+    format!(
+        r#"You are a command-line assistant. Explain the shell command the user gives you.
+
+Format your response EXACTLY as:
+SUMMARY: <one line describing what the whole command does>
+STAGE: <one pipeline stage or chained command, copied exactly>
+PART: <program, flag, argument or operator from that stage, copied exactly> :: <what it does>
+
+Context:
+- OS: {}
+- Shell: {}
+
+Rules:
+- Repeat STAGE for every stage separated by |, &&, || or ;, in order, each followed by its PART lines
+- Give every program, flag, argument, redirection and expansion its own PART line; keep a flag and its value together
+- Keep each description to one concise line
+- Point out anything destructive or surprising in the description of the part responsible"#,
+        get_os(),
+        get_shell()
+    )
+}
+
 fn get_os() -> String {
     let os = env::consts::OS;
     let arch = env::consts::ARCH;
@@ -52,3 +197,116 @@ fn get_cwd() -> String {
         .map(|p| p.display().to_string())
         .unwrap_or_else(|_| "unknown".to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_context() {
+        let context = read_context("pod-a  CrashLoopBackOff\n".as_bytes(), 100).unwrap();
+        assert_eq!(
+            context,
+            Some(StdinContext {
+                content: "pod-a  CrashLoopBackOff\n".to_string(),
+                truncated: false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_read_context_truncates() {
+        let context = read_context("0123456789".as_bytes(), 4).unwrap().unwrap();
+        assert_eq!(context.content, "0123");
+        assert!(context.truncated);
+
+        let context = read_context("0123".as_bytes(), 4).unwrap().unwrap();
+        assert!(!context.truncated);
+    }
+
+    #[test]
+    fn test_read_context_empty() {
+        assert_eq!(read_context(" \n".as_bytes(), 100).unwrap(), None);
+    }
+
+    #[test]
+    fn test_build_user_prompt() {
+        assert_eq!(build_user_prompt("list files", None), "list files");
+
+        let context = StdinContext {
+            content: "FAILED test_a\n".to_string(),
+            truncated: false,
+        };
+        let prompt = build_user_prompt("extract the failing test names", Some(&context));
+        assert!(prompt.contains("<stdin>\nFAILED test_a\n</stdin>"));
+        assert!(prompt.ends_with("Request: extract the failing test names"));
+        assert!(!prompt.contains("truncated"));
+    }
+
+    #[test]
+    fn test_build_system_prompt() {
+        let prompt = build_system_prompt(None);
+        assert!(prompt.contains(&format!("- OS: {}\n", get_os())));
+        assert!(prompt.contains(&format!("Rules:\n{}\n", ONE_COMMAND_RULE)));
+    }
+
+    #[test]
+    fn test_build_alternatives_prompt() {
+        let prompt = build_alternatives_prompt(&build_system_prompt(None), 3);
+        assert!(prompt.contains("give 3 alternative commands, ranked best first"));
+        assert!(prompt.contains("- Make each alternative a single command"));
+        assert!(!prompt.contains("exactly one command"));
+
+        let prompt = build_alternatives_prompt("Custom prompt", 2);
+        assert!(prompt.starts_with("Custom prompt\n\nInstead of one command, give 2"));
+    }
+
+    #[test]
+    fn test_build_policy_prompt() {
+        assert_eq!(build_policy_prompt(&Policy::default()), "");
+
+        let policy = Policy::from_toml(
+            r#"
+[[rule]]
+action = "allow"
+glob = "kubectl get *"
+
+[[rule]]
+action = "deny"
+glob = "kubectl delete *"
+kube_context = "prod*"
+message = "Use the deploy pipeline"
+
+[[rule]]
+action = "confirm"
+glob = "terraform apply*"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            build_policy_prompt(&policy),
+            "\n\nPolicy set by the user's organization, which always applies:\n\
+- Never give a command matching `kubectl delete *` in kubectl contexts matching `prod*` (Use the deploy pipeline)\n\
+- Commands matching `terraform apply*` need confirmation; say so in the explanation"
+        );
+    }
+
+    #[test]
+    fn test_build_fix_prompt() {
+        let prompt = build_fix_prompt(" git psuh origin main\n", Some(1), None);
+        assert!(prompt.starts_with("This command failed with exit status 1:\n"));
+        assert!(prompt.contains("<command>\ngit psuh origin main\n</command>"));
+        assert!(!prompt.contains("<stderr>"));
+
+        let stderr = StdinContext {
+            content: "git: 'psuh' is not a git command.\n".to_string(),
+            truncated: true,
+        };
+        let prompt = build_fix_prompt("git psuh origin main", None, Some(&stderr));
+        assert!(prompt.starts_with("This command failed:\n"));
+        assert!(prompt.contains(
+            "error output (truncated):\n<stderr>\ngit: 'psuh' is not a git command.\n</stderr>"
+        ));
+        assert!(prompt.contains("Request: Give the corrected command"));
+    }
+}
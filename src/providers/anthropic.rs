@@ -1,10 +1,13 @@
-use crate::config::Config;
+use crate::config::{Config, RetryOptions};
+use crate::error::HalpError;
+use crate::providers::retry::send_with_retry;
 use crate::providers::streaming::{create_client, SseProcessor};
-use crate::providers::LlmProvider;
+use crate::providers::{Conversation, LlmProvider, Usage};
 use async_trait::async_trait;
 use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
 use std::io::Write;
+use std::sync::Mutex;
 
 const DEFAULT_API_URL: &str = "https://api.anthropic.com/v1/messages";
 
@@ -12,6 +15,8 @@ pub struct AnthropicProvider {
     api_key: String,
     model: String,
     api_url: String,
+    retry: RetryOptions,
+    usage: Mutex<Option<Usage>>,
 }
 
 #[derive(Serialize)]
@@ -34,7 +39,7 @@ struct Message {
 #[allow(dead_code)]
 enum StreamEvent {
     #[serde(rename = "message_start")]
-    MessageStart { message: serde_json::Value },
+    MessageStart { message: MessageInfo },
     #[serde(rename = "content_block_start")]
     ContentBlockStart {
         index: usize,
@@ -45,7 +50,10 @@ enum StreamEvent {
     #[serde(rename = "content_block_stop")]
     ContentBlockStop { index: usize },
     #[serde(rename = "message_delta")]
-    MessageDelta { delta: serde_json::Value },
+    MessageDelta {
+        delta: serde_json::Value,
+        usage: Option<Usage>,
+    },
     #[serde(rename = "message_stop")]
     MessageStop,
     #[serde(rename = "ping")]
@@ -54,6 +62,11 @@ enum StreamEvent {
     Error { error: ErrorInfo },
 }
 
+#[derive(Deserialize, Debug)]
+struct MessageInfo {
+    usage: Option<Usage>,
+}
+
 #[derive(Deserialize, Debug)]
 struct ContentBlock {
     #[serde(rename = "type")]
@@ -75,9 +88,35 @@ enum Delta {
 
 #[derive(Deserialize, Debug)]
 struct ErrorInfo {
+    #[serde(rename = "type")]
+    error_type: Option<String>,
     message: String,
 }
 
+impl ErrorInfo {
+    /// Errors can arrive mid-stream after a 200, so classify them by type
+    fn into_error(self) -> HalpError {
+        match self.error_type.as_deref() {
+            Some("authentication_error") | Some("permission_error") => {
+                HalpError::Auth(self.message)
+            }
+            Some("rate_limit_error") => HalpError::RateLimit(self.message),
+            Some("overloaded_error") => HalpError::Api {
+                status: Some(529),
+                message: self.message,
+            },
+            Some("api_error") => HalpError::Api {
+                status: Some(500),
+                message: self.message,
+            },
+            _ => HalpError::Api {
+                status: None,
+                message: self.message,
+            },
+        }
+    }
+}
+
 impl AnthropicProvider {
     pub fn new(config: &Config) -> Self {
         Self {
@@ -87,11 +126,30 @@ impl AnthropicProvider {
                 .api_base_url
                 .clone()
                 .unwrap_or_else(|| DEFAULT_API_URL.to_string()),
+            retry: config.retry.clone(),
+            usage: Mutex::new(None),
+        }
+    }
+
+    fn build_request(&self, conversation: &Conversation, system: &str) -> AnthropicRequest {
+        AnthropicRequest {
+            model: self.model.clone(),
+            max_tokens: 1024,
+            system: system.to_string(),
+            messages: conversation
+                .turns
+                .iter()
+                .map(|turn| Message {
+                    role: turn.role.as_str().to_string(),
+                    content: turn.content.clone(),
+                })
+                .collect(),
+            stream: true,
         }
     }
 }
 
-fn extract_text(data: &str) -> Result<Option<String>, String> {
+fn extract_text(data: &str) -> Result<Option<String>, HalpError> {
     match serde_json::from_str::<StreamEvent>(data) {
         Ok(event) => match event {
             StreamEvent::ContentBlockDelta { delta, .. } => {
@@ -101,43 +159,42 @@ fn extract_text(data: &str) -> Result<Option<String>, String> {
                     Ok(None)
                 }
             }
-            StreamEvent::Error { error } => Err(format!("API error: {}", error.message)),
+            StreamEvent::Error { error } => Err(error.into_error()),
             _ => Ok(None),
         },
-        Err(e) => Err(format!("Failed to parse API response: {}", e)),
+        Err(e) => Err(HalpError::Parse(e.to_string())),
+    }
+}
+
+/// Input tokens arrive in `message_start`, the output total in `message_delta`
+fn extract_usage(data: &str) -> Option<Usage> {
+    match serde_json::from_str::<StreamEvent>(data).ok()? {
+        StreamEvent::MessageStart { message } => message.usage,
+        StreamEvent::MessageDelta { usage, .. } => usage,
+        _ => None,
     }
 }
 
 #[async_trait]
 impl LlmProvider for AnthropicProvider {
-    async fn stream_completion(
+    async fn stream_conversation(
         &self,
-        prompt: &str,
+        conversation: &Conversation,
         system: &str,
         output: &mut (dyn Write + Send),
-    ) -> Result<String, String> {
+    ) -> Result<String, HalpError> {
         let client = create_client();
 
-        let request = AnthropicRequest {
-            model: self.model.clone(),
-            max_tokens: 1024,
-            system: system.to_string(),
-            messages: vec![Message {
-                role: "user".to_string(),
-                content: prompt.to_string(),
-            }],
-            stream: true,
-        };
+        let request = self.build_request(conversation, system);
 
-        let response = client
+        let request = client
             .post(&self.api_url)
             .header("x-api-key", &self.api_key)
             .header("anthropic-version", "2023-06-01")
             .header("content-type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| format!("Request failed: {}", e))?;
+            .json(&request);
+
+        let response = send_with_retry(request, &self.retry).await?;
 
         if !response.status().is_success() {
             let status = response.status();
@@ -145,18 +202,76 @@ impl LlmProvider for AnthropicProvider {
                 .text()
                 .await
                 .unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(format!("API error ({}): {}", status, body));
+            return Err(HalpError::from_status(status, body));
         }
 
         let mut processor = SseProcessor::new();
         let mut stream = response.bytes_stream();
+        let mut usage: Option<Usage> = None;
 
         while let Some(chunk) = stream.next().await {
-            let chunk = chunk.map_err(|e| format!("Stream error: {}", e))?;
+            let chunk = chunk?;
             processor.push_chunk(&chunk);
-            processor.process_events_with_output(output, extract_text)?;
+            processor.process_events_with_output(output, |event| {
+                if let Some(u) = extract_usage(&event.data) {
+                    usage.get_or_insert_with(Usage::default).accumulate(u);
+                }
+                extract_text(&event.data)
+            })?;
         }
 
+        *self.usage.lock().unwrap() = usage;
         Ok(processor.into_response())
     }
+
+    fn usage(&self) -> Option<Usage> {
+        *self.usage.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Provider;
+    use crate::providers::follow_up;
+    use serde_json::json;
+
+    #[test]
+    fn test_extract_usage() {
+        let start = r#"{"type":"message_start","message":{"id":"msg_1","usage":{"input_tokens":25,"output_tokens":1}}}"#;
+        let delta = r#"{"type":"message_delta","delta":{"stop_reason":"end_turn"},"usage":{"output_tokens":15}}"#;
+
+        let mut usage = extract_usage(start).unwrap();
+        usage.accumulate(extract_usage(delta).unwrap());
+
+        assert_eq!(
+            usage,
+            Usage {
+                input_tokens: 25,
+                output_tokens: 15
+            }
+        );
+        assert_eq!(
+            extract_usage(
+                r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"ls"}}"#
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_build_request() {
+        let provider = AnthropicProvider::new(&Config::new(Provider::Anthropic));
+        let request = serde_json::to_value(provider.build_request(&follow_up(), "sys")).unwrap();
+
+        assert_eq!(request["system"], "sys");
+        assert_eq!(
+            request["messages"],
+            json!([
+                {"role": "user", "content": "find large files"},
+                {"role": "assistant", "content": "COMMAND: find . -size +100M"},
+                {"role": "user", "content": "now only in /var"},
+            ])
+        );
+    }
 }
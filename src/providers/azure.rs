@@ -0,0 +1,163 @@
+use crate::config::Config;
+use crate::error::HalpError;
+use crate::providers::openai::OpenAIProvider;
+use crate::providers::{Conversation, LlmProvider, Usage};
+use async_trait::async_trait;
+use std::io::Write;
+
+/// An Azure OpenAI deployment. It speaks the OpenAI API, so requests and
+/// streaming are the OpenAI provider's.
+pub struct AzureProvider {
+    inner: OpenAIProvider,
+}
+
+impl AzureProvider {
+    pub fn new(config: &Config) -> Self {
+        let api_url = config.api_base_url.clone().unwrap_or_else(|| {
+            format!(
+                "{}/openai/deployments/{}/chat/completions?api-version={}",
+                config.azure.resource,
+                escape_path_segment(&config.azure.deployment),
+                config.azure.api_version
+            )
+        });
+        Self {
+            inner: OpenAIProvider::azure(config, api_url),
+        }
+    }
+}
+
+/// Percent-encode everything but unreserved characters, so a deployment name
+/// stays one segment of the URL path
+fn escape_path_segment(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[async_trait]
+impl LlmProvider for AzureProvider {
+    async fn stream_conversation(
+        &self,
+        conversation: &Conversation,
+        system: &str,
+        output: &mut (dyn Write + Send),
+    ) -> Result<String, HalpError> {
+        self.inner
+            .stream_conversation(conversation, system, output)
+            .await
+    }
+
+    fn usage(&self) -> Option<Usage> {
+        self.inner.usage()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AzureOptions, MaxTokensField, Provider};
+    use serde_json::json;
+
+    fn config() -> Config {
+        let mut config = Config::new(Provider::Azure);
+        config.api_key = "azure-key".to_string();
+        config.azure = AzureOptions {
+            resource: "https://my-resource.openai.azure.com".to_string(),
+            deployment: "gpt-4o-prod".to_string(),
+            api_version: "2024-10-21".to_string(),
+        };
+        config
+    }
+
+    #[test]
+    fn test_deployment_url_and_auth() {
+        let provider = AzureProvider::new(&config());
+        let request = provider
+            .inner
+            .post(&reqwest::Client::new())
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            request.url().as_str(),
+            "https://my-resource.openai.azure.com/openai/deployments/gpt-4o-prod/chat/completions?api-version=2024-10-21"
+        );
+        assert_eq!(request.headers()["api-key"], "azure-key");
+        assert!(request.headers().get("authorization").is_none());
+    }
+
+    #[test]
+    fn test_deployment_is_escaped() {
+        let mut config = config();
+        config.azure.deployment = "my deployment/v2?x=1".to_string();
+        let provider = AzureProvider::new(&config);
+        let request = provider
+            .inner
+            .post(&reqwest::Client::new())
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            request.url().path(),
+            "/openai/deployments/my%20deployment%2Fv2%3Fx%3D1/chat/completions"
+        );
+        assert_eq!(request.url().query(), Some("api-version=2024-10-21"));
+    }
+
+    #[test]
+    fn test_api_base_url_overrides_deployment_url() {
+        let mut config = config();
+        config.api_base_url = Some("http://localhost:8080/chat".to_string());
+        let provider = AzureProvider::new(&config);
+        let request = provider
+            .inner
+            .post(&reqwest::Client::new())
+            .build()
+            .unwrap();
+
+        assert_eq!(request.url().as_str(), "http://localhost:8080/chat");
+    }
+
+    #[test]
+    fn test_build_request() {
+        let provider = AzureProvider::new(&config());
+        let request = serde_json::to_value(
+            provider
+                .inner
+                .build_request(&Conversation::new("list files"), "sys"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            request,
+            json!({
+                "max_tokens": 1024,
+                "messages": [
+                    {"role": "system", "content": "sys"},
+                    {"role": "user", "content": "list files"},
+                ],
+                "stream": true,
+                "stream_options": {"include_usage": true},
+            })
+        );
+
+        let mut config = config();
+        config.openai.max_tokens_field = MaxTokensField::MaxCompletionTokens;
+        let provider = AzureProvider::new(&config);
+        let request = serde_json::to_value(
+            provider
+                .inner
+                .build_request(&Conversation::new("list files"), "sys"),
+        )
+        .unwrap();
+        assert_eq!(request["max_completion_tokens"], 1024);
+        assert!(request.get("max_tokens").is_none());
+    }
+}
@@ -0,0 +1,194 @@
+use crate::error::HalpError;
+use crate::providers::{Conversation, LlmProvider, Usage};
+use async_trait::async_trait;
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+/// Tries each provider in order, moving on when one is unavailable
+pub struct FallbackChain {
+    providers: Vec<(String, Box<dyn LlmProvider>)>,
+    answered_by: Mutex<Option<usize>>,
+}
+
+impl FallbackChain {
+    /// Build a chain from labelled providers; the first one is the primary
+    pub fn new(providers: Vec<(String, Box<dyn LlmProvider>)>) -> Self {
+        Self {
+            providers,
+            answered_by: Mutex::new(None),
+        }
+    }
+}
+
+/// Remembers whether a provider wrote anything before failing
+struct TrackingWriter<'a> {
+    inner: &'a mut (dyn Write + Send),
+    written: bool,
+}
+
+impl Write for TrackingWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written |= !buf.is_empty();
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[async_trait]
+impl LlmProvider for FallbackChain {
+    async fn stream_conversation(
+        &self,
+        conversation: &Conversation,
+        system: &str,
+        output: &mut (dyn Write + Send),
+    ) -> Result<String, HalpError> {
+        let mut last_error = HalpError::Config("No providers configured".to_string());
+
+        for (i, (label, provider)) in self.providers.iter().enumerate() {
+            let mut writer = TrackingWriter {
+                inner: &mut *output,
+                written: false,
+            };
+            let result = provider
+                .stream_conversation(conversation, system, &mut writer)
+                .await;
+            let partial = writer.written;
+
+            match result {
+                Ok(response) => {
+                    *self.answered_by.lock().unwrap() = Some(i);
+                    return Ok(response);
+                }
+                Err(e) if e.is_unavailable() => {
+                    if let Some((next, _)) = self.providers.get(i + 1) {
+                        // Don't append the notice to a partial answer
+                        let separator = if partial { "\n" } else { "" };
+                        let _ = writeln!(
+                            output,
+                            "{}{} failed ({}), trying {}",
+                            separator, label, e, next
+                        );
+                        let _ = output.flush();
+                    }
+                    last_error = e;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(last_error)
+    }
+
+    /// Only reported when a fallback answered instead of the primary
+    fn answered_by(&self) -> Option<String> {
+        let answered_by = (*self.answered_by.lock().unwrap())?;
+        if answered_by == 0 {
+            return None;
+        }
+        self.providers
+            .get(answered_by)
+            .map(|(label, _)| label.clone())
+    }
+
+    fn usage(&self) -> Option<Usage> {
+        let answered_by = (*self.answered_by.lock().unwrap())?;
+        self.providers.get(answered_by)?.1.usage()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct StubProvider(Result<&'static str, HalpError>);
+
+    #[async_trait]
+    impl LlmProvider for StubProvider {
+        async fn stream_conversation(
+            &self,
+            _conversation: &Conversation,
+            _system: &str,
+            _output: &mut (dyn Write + Send),
+        ) -> Result<String, HalpError> {
+            self.0.clone().map(String::from)
+        }
+    }
+
+    fn chain(results: Vec<Result<&'static str, HalpError>>) -> FallbackChain {
+        FallbackChain::new(
+            results
+                .into_iter()
+                .enumerate()
+                .map(|(i, r)| {
+                    (
+                        format!("p{}", i),
+                        Box::new(StubProvider(r)) as Box<dyn LlmProvider>,
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_primary_success_is_not_a_fallback() {
+        let chain = chain(vec![Ok("first"), Ok("second")]);
+        let mut output = Vec::new();
+
+        let response = chain.stream_completion("q", "s", &mut output).await;
+
+        assert_eq!(response, Ok("first".to_string()));
+        assert_eq!(chain.answered_by(), None);
+        assert!(output.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_on_unavailable() {
+        let chain = chain(vec![
+            Err(HalpError::Api {
+                status: Some(503),
+                message: "down".to_string(),
+            }),
+            Err(HalpError::Network("dns error".to_string())),
+            Ok("third"),
+        ]);
+        let mut output = Vec::new();
+
+        let response = chain.stream_completion("q", "s", &mut output).await;
+
+        assert_eq!(response, Ok("third".to_string()));
+        assert_eq!(chain.answered_by(), Some("p2".to_string()));
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("p0 failed"));
+        assert!(output.contains("trying p2"));
+    }
+
+    #[tokio::test]
+    async fn test_stops_on_request_error() {
+        let chain = chain(vec![
+            Err(HalpError::Auth("bad key".to_string())),
+            Ok("second"),
+        ]);
+        let mut output = Vec::new();
+
+        let response = chain.stream_completion("q", "s", &mut output).await;
+
+        assert_eq!(response, Err(HalpError::Auth("bad key".to_string())));
+        assert_eq!(chain.answered_by(), None);
+    }
+
+    #[tokio::test]
+    async fn test_returns_last_error_when_all_fail() {
+        let chain = chain(vec![
+            Err(HalpError::Timeout("timeout".to_string())),
+            Err(HalpError::RateLimit("slow down".to_string())),
+        ]);
+        let mut output = Vec::new();
+
+        let response = chain.stream_completion("q", "s", &mut output).await;
+
+        assert_eq!(response, Err(HalpError::RateLimit("slow down".to_string())));
+    }
+}
@@ -1,10 +1,13 @@
-use crate::config::Config;
+use crate::config::{Config, RetryOptions};
+use crate::error::HalpError;
+use crate::providers::retry::send_with_retry;
 use crate::providers::streaming::{create_client, SseProcessor};
-use crate::providers::LlmProvider;
+use crate::providers::{Conversation, LlmProvider, Role, Usage};
 use async_trait::async_trait;
 use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
 use std::io::Write;
+use std::sync::Mutex;
 
 const DEFAULT_API_BASE: &str = "https://generativelanguage.googleapis.com/v1beta/models";
 
@@ -12,6 +15,8 @@ pub struct GeminiProvider {
     api_key: String,
     model: String,
     api_url: Option<String>,
+    retry: RetryOptions,
+    usage: Mutex<Option<Usage>>,
 }
 
 #[derive(Serialize)]
@@ -27,6 +32,7 @@ struct SystemInstruction {
 
 #[derive(Serialize)]
 struct Content {
+    role: String,
     parts: Vec<Part>,
 }
 
@@ -36,8 +42,19 @@ struct Part {
 }
 
 #[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
 struct StreamChunk {
     candidates: Option<Vec<Candidate>>,
+    usage_metadata: Option<UsageMetadata>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct UsageMetadata {
+    #[serde(default)]
+    prompt_token_count: u32,
+    #[serde(default)]
+    candidates_token_count: u32,
 }
 
 #[derive(Deserialize, Debug)]
@@ -61,6 +78,8 @@ impl GeminiProvider {
             api_key: config.api_key.clone(),
             model: config.model.clone(),
             api_url: config.api_base_url.clone(),
+            retry: config.retry.clone(),
+            usage: Mutex::new(None),
         }
     }
 
@@ -72,9 +91,34 @@ impl GeminiProvider {
             )
         })
     }
+
+    fn build_request(&self, conversation: &Conversation, system: &str) -> GeminiRequest {
+        GeminiRequest {
+            system_instruction: SystemInstruction {
+                parts: vec![Part {
+                    text: system.to_string(),
+                }],
+            },
+            contents: conversation
+                .turns
+                .iter()
+                .map(|turn| Content {
+                    // Gemini calls the assistant "model"
+                    role: match turn.role {
+                        Role::User => "user",
+                        Role::Assistant => "model",
+                    }
+                    .to_string(),
+                    parts: vec![Part {
+                        text: turn.content.clone(),
+                    }],
+                })
+                .collect(),
+        }
+    }
 }
 
-fn extract_text(data: &str) -> Result<Option<String>, String> {
+fn extract_text(data: &str) -> Result<Option<String>, HalpError> {
     match serde_json::from_str::<StreamChunk>(data) {
         Ok(chunk) => {
             let text = chunk
@@ -86,43 +130,42 @@ fn extract_text(data: &str) -> Result<Option<String>, String> {
                 .and_then(|p| p.text);
             Ok(text)
         }
-        Err(e) => Err(format!("Failed to parse API response: {}", e)),
+        Err(e) => Err(HalpError::Parse(e.to_string())),
     }
 }
 
+/// Every chunk carries the running totals so far
+fn extract_usage(data: &str) -> Option<Usage> {
+    let usage = serde_json::from_str::<StreamChunk>(data)
+        .ok()?
+        .usage_metadata?;
+    Some(Usage {
+        input_tokens: usage.prompt_token_count,
+        output_tokens: usage.candidates_token_count,
+    })
+}
+
 #[async_trait]
 impl LlmProvider for GeminiProvider {
-    async fn stream_completion(
+    async fn stream_conversation(
         &self,
-        prompt: &str,
+        conversation: &Conversation,
         system: &str,
         output: &mut (dyn Write + Send),
-    ) -> Result<String, String> {
+    ) -> Result<String, HalpError> {
         let client = create_client();
 
-        let request = GeminiRequest {
-            system_instruction: SystemInstruction {
-                parts: vec![Part {
-                    text: system.to_string(),
-                }],
-            },
-            contents: vec![Content {
-                parts: vec![Part {
-                    text: prompt.to_string(),
-                }],
-            }],
-        };
+        let request = self.build_request(conversation, system);
 
         let url = self.build_url();
 
-        let response = client
+        let request = client
             .post(&url)
             .header("x-goog-api-key", &self.api_key)
             .header("content-type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| format!("Request failed: {}", e))?;
+            .json(&request);
+
+        let response = send_with_retry(request, &self.retry).await?;
 
         if !response.status().is_success() {
             let status = response.status();
@@ -130,18 +173,57 @@ impl LlmProvider for GeminiProvider {
                 .text()
                 .await
                 .unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(format!("API error ({}): {}", status, body));
+            return Err(HalpError::from_status(status, body));
         }
 
         let mut processor = SseProcessor::new();
         let mut stream = response.bytes_stream();
 
+        let mut usage: Option<Usage> = None;
+
         while let Some(chunk) = stream.next().await {
-            let chunk = chunk.map_err(|e| format!("Stream error: {}", e))?;
+            let chunk = chunk?;
             processor.push_chunk(&chunk);
-            processor.process_events_with_output(output, extract_text)?;
+            processor.process_events_with_output(output, |event| {
+                if let Some(u) = extract_usage(&event.data) {
+                    usage.get_or_insert_with(Usage::default).accumulate(u);
+                }
+                extract_text(&event.data)
+            })?;
         }
 
+        *self.usage.lock().unwrap() = usage;
         Ok(processor.into_response())
     }
+
+    fn usage(&self) -> Option<Usage> {
+        *self.usage.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Provider;
+    use crate::providers::follow_up;
+    use serde_json::json;
+
+    #[test]
+    fn test_build_request() {
+        let provider = GeminiProvider::new(&Config::new(Provider::Gemini));
+        let request = serde_json::to_value(provider.build_request(&follow_up(), "sys")).unwrap();
+
+        assert_eq!(
+            request["system_instruction"],
+            json!({"parts": [{"text": "sys"}]})
+        );
+        assert_eq!(
+            request["contents"],
+            json!([
+                {"role": "user", "parts": [{"text": "find large files"}]},
+                {"role": "model", "parts": [{"text": "COMMAND: find . -size +100M"}]},
+                {"role": "user", "parts": [{"text": "now only in /var"}]},
+            ])
+        );
+    }
 }
@@ -1,26 +1,340 @@
 pub mod anthropic;
+pub mod azure;
+pub mod fallback;
 pub mod gemini;
+pub mod ollama;
 pub mod openai;
+pub mod race;
+mod retry;
 mod streaming;
 
-use crate::config::{Config, Provider as ProviderType};
+use crate::config::{AzureOptions, Config, OpenAIOptions, Provider as ProviderType, RetryOptions};
+use crate::error::HalpError;
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use std::io::Write;
 
+/// Token counts the provider reported for a completion
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Usage {
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+}
+
+impl Usage {
+    /// Fold in counts from a later stream event. Providers report running
+    /// totals, some split across events, so keep the largest count seen.
+    pub(crate) fn accumulate(&mut self, other: Usage) {
+        self.input_tokens = self.input_tokens.max(other.input_tokens);
+        self.output_tokens = self.output_tokens.max(other.output_tokens);
+    }
+}
+
+/// Who a [`Turn`] of a conversation is from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+impl Role {
+    /// The role's name in the Anthropic, OpenAI and Ollama APIs
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Assistant => "assistant",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Turn {
+    pub role: Role,
+    pub content: String,
+}
+
+/// The messages to send a provider, oldest first: user turns, with the
+/// assistant's earlier replies between them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Conversation {
+    pub turns: Vec<Turn>,
+}
+
+impl Conversation {
+    /// A conversation of a single user message
+    pub fn new(prompt: impl Into<String>) -> Self {
+        let mut conversation = Self::default();
+        conversation.push_user(prompt);
+        conversation
+    }
+
+    pub fn push_user(&mut self, content: impl Into<String>) {
+        self.turns.push(Turn {
+            role: Role::User,
+            content: content.into(),
+        });
+    }
+
+    pub fn push_assistant(&mut self, content: impl Into<String>) {
+        self.turns.push(Turn {
+            role: Role::Assistant,
+            content: content.into(),
+        });
+    }
+}
+
+/// A request, the reply to it and a follow-up, for testing how providers
+/// send conversations
+#[cfg(test)]
+pub(crate) fn follow_up() -> Conversation {
+    let mut conversation = Conversation::new("find large files");
+    conversation.push_assistant("COMMAND: find . -size +100M");
+    conversation.push_user("now only in /var");
+    conversation
+}
+
 #[async_trait]
 pub trait LlmProvider: Send + Sync {
+    /// Stream the reply to a conversation to `output`, returning all of it
+    async fn stream_conversation(
+        &self,
+        conversation: &Conversation,
+        system: &str,
+        output: &mut (dyn Write + Send),
+    ) -> Result<String, HalpError>;
+
+    /// Stream the reply to a single user message
     async fn stream_completion(
         &self,
         prompt: &str,
         system: &str,
         output: &mut (dyn Write + Send),
-    ) -> Result<String, String>;
+    ) -> Result<String, HalpError> {
+        self.stream_conversation(&Conversation::new(prompt), system, output)
+            .await
+    }
+
+    /// Label of the provider that actually answered the last request, for
+    /// providers that delegate to others
+    fn answered_by(&self) -> Option<String> {
+        None
+    }
+
+    /// Token usage of the last successful request, if the provider reported it
+    fn usage(&self) -> Option<Usage> {
+        None
+    }
 }
 
 pub fn create_provider(config: &Config) -> Box<dyn LlmProvider> {
     match config.provider {
         ProviderType::Anthropic => Box::new(anthropic::AnthropicProvider::new(config)),
-        ProviderType::OpenAI => Box::new(openai::OpenAIProvider::new(config)),
+        ProviderType::OpenAI | ProviderType::OpenAICompatible => {
+            Box::new(openai::OpenAIProvider::new(config))
+        }
         ProviderType::Gemini => Box::new(gemini::GeminiProvider::new(config)),
+        ProviderType::Ollama => Box::new(ollama::OllamaProvider::new(config)),
+        ProviderType::Azure => Box::new(azure::AzureProvider::new(config)),
+    }
+}
+
+/// Create the configured provider followed by its fallbacks, either tried
+/// in order or raced against each other
+pub fn create_provider_chain(config: &Config, race: bool) -> Box<dyn LlmProvider> {
+    let providers = std::iter::once(config)
+        .chain(config.fallbacks.iter())
+        .map(|c| (c.label(), create_provider(c)));
+
+    if race {
+        Box::new(race::Race::new(
+            providers.map(|(label, p)| (label, p.into())).collect(),
+        ))
+    } else {
+        Box::new(fallback::FallbackChain::new(providers.collect()))
+    }
+}
+
+/// Builds an [`LlmProvider`] in code, starting from a provider's defaults or a
+/// loaded [`Config`]
+///
+/// ```no_run
+/// use halp::config::Provider;
+/// use halp::providers::{LlmProvider, ProviderBuilder};
+///
+/// # async fn run() -> Result<(), halp::HalpError> {
+/// let provider = ProviderBuilder::new(Provider::Anthropic)
+///     .api_key(std::env::var("ANTHROPIC_API_KEY").unwrap_or_default())
+///     .build()?;
+/// let system = halp::build_system_prompt(None);
+/// let response = provider
+///     .stream_completion("list files by size", &system, &mut std::io::sink())
+///     .await?;
+/// println!("{:?}", halp::parse_response(&response).command());
+/// # Ok(())
+/// # }
+/// ```
+pub struct ProviderBuilder {
+    config: Config,
+}
+
+impl ProviderBuilder {
+    pub fn new(provider: ProviderType) -> Self {
+        Self::from_config(Config::new(provider))
+    }
+
+    /// Start from a loaded configuration, including its fallbacks
+    pub fn from_config(config: Config) -> Self {
+        Self { config }
+    }
+
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.config.model = model.into();
+        self
+    }
+
+    pub fn api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.config.api_key = api_key.into();
+        self
+    }
+
+    pub fn api_base_url(mut self, api_base_url: impl Into<String>) -> Self {
+        self.config.api_base_url = Some(api_base_url.into());
+        self
+    }
+
+    pub fn openai_options(mut self, options: OpenAIOptions) -> Self {
+        self.config.openai = options;
+        self
+    }
+
+    pub fn azure_options(mut self, options: AzureOptions) -> Self {
+        self.config.azure = options;
+        self
+    }
+
+    pub fn retry(mut self, options: RetryOptions) -> Self {
+        self.config.retry = options;
+        self
+    }
+
+    /// Add a provider to try when the previous ones are unavailable
+    pub fn fallback(mut self, config: Config) -> Self {
+        self.config.fallbacks.push(config);
+        self
+    }
+
+    /// Query all providers at once instead of one after another
+    pub fn race(mut self, race: bool) -> Self {
+        self.config.race = race;
+        self
+    }
+
+    pub fn build(self) -> Result<Box<dyn LlmProvider>, HalpError> {
+        for config in std::iter::once(&self.config).chain(&self.config.fallbacks) {
+            validate(config)?;
+        }
+        Ok(create_provider_chain(&self.config, self.config.race))
+    }
+}
+
+/// Catch settings that `Config::load` would have rejected
+fn validate(config: &Config) -> Result<(), HalpError> {
+    if config.model.is_empty() {
+        return Err(HalpError::Config(format!(
+            "The {} provider requires a model",
+            config.provider
+        )));
+    }
+
+    match config.provider {
+        ProviderType::OpenAICompatible if config.api_base_url.is_none() => Err(HalpError::Config(
+            "The openai_compatible provider requires api_base_url".to_string(),
+        )),
+        ProviderType::Azure
+            if config.api_base_url.is_none()
+                && (config.azure.resource.is_empty() || config.azure.deployment.is_empty()) =>
+        {
+            Err(HalpError::Config(
+                "The azure provider requires a resource and deployment".to_string(),
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn azure(resource: &str, deployment: &str) -> Config {
+        let mut config = Config::new(ProviderType::Azure);
+        config.model = "gpt-4o".to_string();
+        config.azure.resource = resource.to_string();
+        config.azure.deployment = deployment.to_string();
+        config
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(&Config::new(ProviderType::Anthropic)).is_ok());
+
+        let mut no_model = Config::new(ProviderType::Ollama);
+        no_model.model.clear();
+        assert!(matches!(validate(&no_model), Err(HalpError::Config(_))));
+
+        let mut compatible = Config::new(ProviderType::OpenAICompatible);
+        compatible.model = "llama3".to_string();
+        assert!(matches!(validate(&compatible), Err(HalpError::Config(_))));
+        compatible.api_base_url = Some("http://localhost:8000/v1".to_string());
+        assert!(validate(&compatible).is_ok());
+    }
+
+    #[test]
+    fn test_validate_azure() {
+        assert!(validate(&azure("https://r.openai.azure.com", "gpt-4o")).is_ok());
+        assert!(matches!(
+            validate(&azure("https://r.openai.azure.com", "")),
+            Err(HalpError::Config(_))
+        ));
+        assert!(matches!(
+            validate(&azure("", "gpt-4o")),
+            Err(HalpError::Config(_))
+        ));
+
+        let mut base_url = azure("", "");
+        base_url.api_base_url = Some("https://gateway.example.com/chat".to_string());
+        assert!(validate(&base_url).is_ok());
+    }
+
+    #[test]
+    fn test_builder_settings() {
+        let builder = ProviderBuilder::new(ProviderType::Ollama)
+            .model("qwen2.5-coder")
+            .api_key("key")
+            .api_base_url("http://gpu-box:11434")
+            .fallback(Config::new(ProviderType::Anthropic))
+            .race(true);
+
+        assert_eq!(builder.config.provider, ProviderType::Ollama);
+        assert_eq!(builder.config.model, "qwen2.5-coder");
+        assert_eq!(builder.config.api_key, "key");
+        assert_eq!(
+            builder.config.api_base_url.as_deref(),
+            Some("http://gpu-box:11434")
+        );
+        assert_eq!(builder.config.fallbacks.len(), 1);
+        assert!(builder.config.race);
+        assert!(builder.build().is_ok());
+    }
+
+    #[test]
+    fn test_builder_validates_fallbacks() {
+        let primary = ProviderBuilder::new(ProviderType::Ollama).model("");
+        assert!(matches!(primary.build(), Err(HalpError::Config(_))));
+
+        let fallback = ProviderBuilder::new(ProviderType::Ollama)
+            .fallback(Config::new(ProviderType::OpenAICompatible));
+        assert!(matches!(fallback.build(), Err(HalpError::Config(_))));
     }
 }
@@ -0,0 +1,229 @@
+use crate::config::{Config, RetryOptions};
+use crate::error::HalpError;
+use crate::providers::retry::send_with_retry;
+use crate::providers::streaming::{create_client, NdjsonProcessor};
+use crate::providers::{Conversation, LlmProvider, Usage};
+use async_trait::async_trait;
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::sync::Mutex;
+
+const DEFAULT_API_URL: &str = "http://localhost:11434/api/chat";
+
+pub struct OllamaProvider {
+    api_key: String,
+    model: String,
+    api_url: String,
+    retry: RetryOptions,
+    usage: Mutex<Option<Usage>>,
+}
+
+#[derive(Serialize)]
+struct OllamaRequest {
+    model: String,
+    messages: Vec<Message>,
+    stream: bool,
+    options: Options,
+}
+
+#[derive(Serialize)]
+struct Message {
+    role: String,
+    content: String,
+}
+
+#[derive(Serialize)]
+struct Options {
+    num_predict: u32,
+}
+
+#[derive(Deserialize, Debug)]
+struct StreamChunk {
+    message: Option<ResponseMessage>,
+    error: Option<String>,
+    /// Only set on the final `done` chunk
+    prompt_eval_count: Option<u32>,
+    eval_count: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ResponseMessage {
+    content: Option<String>,
+}
+
+impl OllamaProvider {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            api_key: config.api_key.clone(),
+            model: config.model.clone(),
+            api_url: config
+                .api_base_url
+                .clone()
+                .unwrap_or_else(|| DEFAULT_API_URL.to_string()),
+            retry: config.retry.clone(),
+            usage: Mutex::new(None),
+        }
+    }
+
+    fn build_request(&self, conversation: &Conversation, system: &str) -> OllamaRequest {
+        OllamaRequest {
+            model: self.model.clone(),
+            messages: vec![Message {
+                role: "system".to_string(),
+                content: system.to_string(),
+            }]
+            .into_iter()
+            .chain(conversation.turns.iter().map(|turn| Message {
+                role: turn.role.as_str().to_string(),
+                content: turn.content.clone(),
+            }))
+            .collect(),
+            stream: true,
+            options: Options { num_predict: 1024 },
+        }
+    }
+}
+
+fn extract_text(line: &str) -> Result<Option<String>, HalpError> {
+    match serde_json::from_str::<StreamChunk>(line) {
+        Ok(chunk) => {
+            if let Some(error) = chunk.error {
+                return Err(HalpError::Api {
+                    status: None,
+                    message: error,
+                });
+            }
+            Ok(chunk.message.and_then(|m| m.content))
+        }
+        Err(e) => Err(HalpError::Parse(e.to_string())),
+    }
+}
+
+fn extract_usage(line: &str) -> Option<Usage> {
+    let chunk = serde_json::from_str::<StreamChunk>(line).ok()?;
+    if chunk.prompt_eval_count.is_none() && chunk.eval_count.is_none() {
+        return None;
+    }
+    Some(Usage {
+        input_tokens: chunk.prompt_eval_count.unwrap_or(0),
+        output_tokens: chunk.eval_count.unwrap_or(0),
+    })
+}
+
+#[async_trait]
+impl LlmProvider for OllamaProvider {
+    async fn stream_conversation(
+        &self,
+        conversation: &Conversation,
+        system: &str,
+        output: &mut (dyn Write + Send),
+    ) -> Result<String, HalpError> {
+        let client = create_client();
+
+        let request = self.build_request(conversation, system);
+
+        let mut builder = client
+            .post(&self.api_url)
+            .header("content-type", "application/json");
+
+        // Local Ollama needs no key, but a reverse proxy in front of it might
+        if !self.api_key.is_empty() {
+            builder = builder.header("Authorization", format!("Bearer {}", self.api_key));
+        }
+
+        let response = send_with_retry(builder.json(&request), &self.retry).await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(HalpError::from_status(status, body));
+        }
+
+        let mut processor = NdjsonProcessor::new();
+        let mut stream = response.bytes_stream();
+
+        let mut usage: Option<Usage> = None;
+        let mut handle_line = |line: &str| {
+            if let Some(u) = extract_usage(line) {
+                usage.get_or_insert_with(Usage::default).accumulate(u);
+            }
+            extract_text(line)
+        };
+
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            processor.push_chunk(&chunk);
+            processor.process_lines_with_output(output, &mut handle_line)?;
+        }
+        processor.finish_with_output(output, &mut handle_line)?;
+
+        *self.usage.lock().unwrap() = usage;
+        Ok(processor.into_response())
+    }
+
+    fn usage(&self) -> Option<Usage> {
+        *self.usage.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Provider;
+    use crate::providers::follow_up;
+    use serde_json::json;
+
+    #[test]
+    fn test_build_request() {
+        let provider = OllamaProvider::new(&Config::new(Provider::Ollama));
+        let request = serde_json::to_value(provider.build_request(&follow_up(), "sys")).unwrap();
+
+        assert_eq!(request["model"], "llama3.2");
+        assert_eq!(request["stream"], true);
+        assert_eq!(
+            request["messages"],
+            json!([
+                {"role": "system", "content": "sys"},
+                {"role": "user", "content": "find large files"},
+                {"role": "assistant", "content": "COMMAND: find . -size +100M"},
+                {"role": "user", "content": "now only in /var"},
+            ])
+        );
+    }
+
+    #[test]
+    fn test_extract_text() {
+        let chunk =
+            r#"{"model":"llama3.2","message":{"role":"assistant","content":"ls"},"done":false}"#;
+        assert_eq!(extract_text(chunk), Ok(Some("ls".to_string())));
+
+        let error = r#"{"error":"model 'llama9' not found"}"#;
+        assert_eq!(
+            extract_text(error),
+            Err(HalpError::Api {
+                status: None,
+                message: "model 'llama9' not found".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_extract_usage() {
+        let done = r#"{"model":"llama3.2","message":{"role":"assistant","content":""},"done":true,"prompt_eval_count":26,"eval_count":12}"#;
+        assert_eq!(
+            extract_usage(done),
+            Some(Usage {
+                input_tokens: 26,
+                output_tokens: 12
+            })
+        );
+
+        let chunk =
+            r#"{"model":"llama3.2","message":{"role":"assistant","content":"ls"},"done":false}"#;
+        assert_eq!(extract_usage(chunk), None);
+    }
+}
@@ -1,25 +1,45 @@
-use crate::config::Config;
+use crate::config::{Config, MaxTokensField, OpenAIOptions, Provider, RetryOptions};
+use crate::error::HalpError;
+use crate::providers::retry::send_with_retry;
 use crate::providers::streaming::{create_client, SseProcessor};
-use crate::providers::LlmProvider;
+use crate::providers::{Conversation, LlmProvider, Usage};
 use async_trait::async_trait;
 use futures_util::StreamExt;
 use serde::{Deserialize, Serialize};
 use std::io::Write;
+use std::sync::Mutex;
 
 const DEFAULT_API_URL: &str = "https://api.openai.com/v1/chat/completions";
 
 pub struct OpenAIProvider {
     api_key: String,
-    model: String,
+    /// Left out for Azure, which picks the model from the deployment in the URL
+    model: Option<String>,
     api_url: String,
+    options: OpenAIOptions,
+    retry: RetryOptions,
+    /// Ask for a final usage chunk, which not every compatible server accepts
+    include_usage: bool,
+    usage: Mutex<Option<Usage>>,
 }
 
 #[derive(Serialize)]
-struct OpenAIRequest {
-    model: String,
-    max_tokens: u32,
+pub(super) struct OpenAIRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_completion_tokens: Option<u32>,
     messages: Vec<Message>,
     stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+}
+
+#[derive(Serialize)]
+struct StreamOptions {
+    include_usage: bool,
 }
 
 #[derive(Serialize)]
@@ -31,10 +51,20 @@ struct Message {
 #[derive(Deserialize, Debug)]
 struct StreamChunk {
     choices: Vec<Choice>,
+    usage: Option<ChunkUsage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChunkUsage {
+    #[serde(default)]
+    prompt_tokens: u32,
+    #[serde(default)]
+    completion_tokens: u32,
 }
 
 #[derive(Deserialize, Debug)]
 struct Choice {
+    #[serde(default)]
     delta: DeltaContent,
 }
 
@@ -47,59 +77,125 @@ impl OpenAIProvider {
     pub fn new(config: &Config) -> Self {
         Self {
             api_key: config.api_key.clone(),
-            model: config.model.clone(),
+            model: Some(config.model.clone()),
             api_url: config
                 .api_base_url
                 .clone()
                 .unwrap_or_else(|| DEFAULT_API_URL.to_string()),
+            options: config.openai.clone(),
+            retry: config.retry.clone(),
+            include_usage: config.provider == Provider::OpenAI,
+            usage: Mutex::new(None),
+        }
+    }
+
+    /// An Azure OpenAI deployment at `api_url`: the same API, but with the
+    /// key in an `api-key` header and no model in the request
+    pub(crate) fn azure(config: &Config, api_url: String) -> Self {
+        Self {
+            api_key: config.api_key.clone(),
+            model: None,
+            api_url,
+            options: OpenAIOptions {
+                auth_header: "api-key".to_string(),
+                auth_scheme: String::new(),
+                max_tokens_field: config.openai.max_tokens_field,
+                ..OpenAIOptions::default()
+            },
+            retry: config.retry.clone(),
+            include_usage: true,
+            usage: Mutex::new(None),
+        }
+    }
+
+    fn auth_value(&self) -> String {
+        if self.options.auth_scheme.is_empty() {
+            self.api_key.clone()
+        } else {
+            format!("{} {}", self.options.auth_scheme, self.api_key)
+        }
+    }
+
+    pub(super) fn build_request(&self, conversation: &Conversation, system: &str) -> OpenAIRequest {
+        let max_tokens = 1024;
+        let (max_tokens, max_completion_tokens) = match self.options.max_tokens_field {
+            MaxTokensField::MaxTokens => (Some(max_tokens), None),
+            MaxTokensField::MaxCompletionTokens => (None, Some(max_tokens)),
+        };
+
+        OpenAIRequest {
+            model: self.model.clone(),
+            max_tokens,
+            max_completion_tokens,
+            messages: std::iter::once(Message {
+                role: "system".to_string(),
+                content: system.to_string(),
+            })
+            .chain(conversation.turns.iter().map(|turn| Message {
+                role: turn.role.as_str().to_string(),
+                content: turn.content.clone(),
+            }))
+            .collect(),
+            stream: true,
+            stream_options: self.include_usage.then_some(StreamOptions {
+                include_usage: true,
+            }),
         }
     }
+
+    /// A POST to the endpoint with the configured query parameters, auth
+    /// and extra headers
+    pub(super) fn post(&self, client: &reqwest::Client) -> reqwest::RequestBuilder {
+        let mut builder = client
+            .post(&self.api_url)
+            .query(&self.options.query_params)
+            .header("content-type", "application/json");
+
+        // Keyless servers (llama.cpp, vLLM) get no auth header at all
+        if !self.api_key.is_empty() {
+            builder = builder.header(self.options.auth_header.as_str(), self.auth_value());
+        }
+
+        for (name, value) in &self.options.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        builder
+    }
 }
 
-fn extract_text(data: &str) -> Result<Option<String>, String> {
+fn extract_text(data: &str) -> Result<Option<String>, HalpError> {
     match serde_json::from_str::<StreamChunk>(data) {
         Ok(chunk) => Ok(chunk
             .choices
             .first()
             .and_then(|c| c.delta.content.clone())),
-        Err(e) => Err(format!("Failed to parse API response: {}", e)),
+        Err(e) => Err(HalpError::Parse(e.to_string())),
     }
 }
 
+/// Usage comes in a final chunk with no choices, when the server sends it at all
+fn extract_usage(data: &str) -> Option<Usage> {
+    let usage = serde_json::from_str::<StreamChunk>(data).ok()?.usage?;
+    Some(Usage {
+        input_tokens: usage.prompt_tokens,
+        output_tokens: usage.completion_tokens,
+    })
+}
+
 #[async_trait]
 impl LlmProvider for OpenAIProvider {
-    async fn stream_completion(
+    async fn stream_conversation(
         &self,
-        prompt: &str,
+        conversation: &Conversation,
         system: &str,
         output: &mut (dyn Write + Send),
-    ) -> Result<String, String> {
+    ) -> Result<String, HalpError> {
         let client = create_client();
 
-        let request = OpenAIRequest {
-            model: self.model.clone(),
-            max_tokens: 1024,
-            messages: vec![
-                Message {
-                    role: "system".to_string(),
-                    content: system.to_string(),
-                },
-                Message {
-                    role: "user".to_string(),
-                    content: prompt.to_string(),
-                },
-            ],
-            stream: true,
-        };
+        let request = self.build_request(conversation, system);
+        let request = self.post(&client).json(&request);
 
-        let response = client
-            .post(&self.api_url)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("content-type", "application/json")
-            .json(&request)
-            .send()
-            .await
-            .map_err(|e| format!("Request failed: {}", e))?;
+        let response = send_with_retry(request, &self.retry).await?;
 
         if !response.status().is_success() {
             let status = response.status();
@@ -107,18 +203,54 @@ impl LlmProvider for OpenAIProvider {
                 .text()
                 .await
                 .unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(format!("API error ({}): {}", status, body));
+            return Err(HalpError::from_status(status, body));
         }
 
         let mut processor = SseProcessor::new();
         let mut stream = response.bytes_stream();
 
+        let mut usage: Option<Usage> = None;
+
         while let Some(chunk) = stream.next().await {
-            let chunk = chunk.map_err(|e| format!("Stream error: {}", e))?;
+            let chunk = chunk?;
             processor.push_chunk(&chunk);
-            processor.process_events_with_output(output, extract_text)?;
+            processor.process_events_with_output(output, |event| {
+                if let Some(u) = extract_usage(&event.data) {
+                    usage.get_or_insert_with(Usage::default).accumulate(u);
+                }
+                extract_text(&event.data)
+            })?;
         }
 
+        *self.usage.lock().unwrap() = usage;
         Ok(processor.into_response())
     }
+
+    fn usage(&self) -> Option<Usage> {
+        *self.usage.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::follow_up;
+    use serde_json::json;
+
+    #[test]
+    fn test_build_request() {
+        let provider = OpenAIProvider::new(&Config::new(Provider::OpenAI));
+        let request = serde_json::to_value(provider.build_request(&follow_up(), "sys")).unwrap();
+
+        assert_eq!(
+            request["messages"],
+            json!([
+                {"role": "system", "content": "sys"},
+                {"role": "user", "content": "find large files"},
+                {"role": "assistant", "content": "COMMAND: find . -size +100M"},
+                {"role": "user", "content": "now only in /var"},
+            ])
+        );
+        assert_eq!(request["stream_options"], json!({"include_usage": true}));
+    }
 }
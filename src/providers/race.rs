@@ -0,0 +1,369 @@
+use crate::error::HalpError;
+use crate::output::{has_complete_command, parse_response};
+use crate::providers::{Conversation, LlmProvider, Usage};
+use async_trait::async_trait;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::task::JoinHandle;
+
+/// Sends the request to every provider at once and streams whichever one
+/// produces a usable command first, cancelling the rest
+pub struct Race {
+    providers: Vec<(String, Arc<dyn LlmProvider>)>,
+    winner: Mutex<Option<usize>>,
+}
+
+enum RaceEvent {
+    Text(usize, String),
+    Done(usize, Result<String, HalpError>),
+}
+
+/// Forwards everything a racer writes to the race coordinator
+struct ChannelWriter {
+    index: usize,
+    tx: UnboundedSender<RaceEvent>,
+}
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf).into_owned();
+        let _ = self.tx.send(RaceEvent::Text(self.index, text));
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Aborts the racer tasks when the race ends or is itself cancelled
+struct Racers(Vec<JoinHandle<()>>);
+
+impl Drop for Racers {
+    fn drop(&mut self) {
+        for handle in &self.0 {
+            handle.abort();
+        }
+    }
+}
+
+impl Race {
+    pub fn new(providers: Vec<(String, Arc<dyn LlmProvider>)>) -> Self {
+        Self {
+            providers,
+            winner: Mutex::new(None),
+        }
+    }
+
+    /// Cancel every other racer, so they stop using tokens, and show what
+    /// the winner has streamed so far
+    fn declare_winner(
+        &self,
+        index: usize,
+        racers: &Racers,
+        buffer: &str,
+        output: &mut (dyn Write + Send),
+    ) -> usize {
+        for (i, handle) in racers.0.iter().enumerate() {
+            if i != index {
+                handle.abort();
+            }
+        }
+        *self.winner.lock().unwrap() = Some(index);
+        let _ = write!(output, "{}", buffer);
+        let _ = output.flush();
+        index
+    }
+}
+
+#[async_trait]
+impl LlmProvider for Race {
+    async fn stream_conversation(
+        &self,
+        conversation: &Conversation,
+        system: &str,
+        output: &mut (dyn Write + Send),
+    ) -> Result<String, HalpError> {
+        let (tx, mut rx) = unbounded_channel();
+
+        let racers = Racers(
+            self.providers
+                .iter()
+                .enumerate()
+                .map(|(index, (_, provider))| {
+                    let provider = provider.clone();
+                    let conversation = conversation.clone();
+                    let system = system.to_string();
+                    let tx = tx.clone();
+                    tokio::spawn(async move {
+                        let mut writer = ChannelWriter {
+                            index,
+                            tx: tx.clone(),
+                        };
+                        let result = provider
+                            .stream_conversation(&conversation, &system, &mut writer)
+                            .await;
+                        let _ = tx.send(RaceEvent::Done(index, result));
+                    })
+                })
+                .collect(),
+        );
+        drop(tx);
+
+        let mut buffers = vec![String::new(); self.providers.len()];
+        let mut winner: Option<usize> = None;
+        // Responses that finished without a recognizable command, kept in case nobody does better
+        let mut unparsed: Option<(usize, String)> = None;
+        let mut last_error = HalpError::Config("No providers configured".to_string());
+
+        while let Some(event) = rx.recv().await {
+            match event {
+                RaceEvent::Text(index, text) => match winner {
+                    Some(w) if w == index => {
+                        let _ = write!(output, "{}", text);
+                        let _ = output.flush();
+                    }
+                    Some(_) => {}
+                    None => {
+                        buffers[index].push_str(&text);
+                        if has_complete_command(&buffers[index]) {
+                            winner =
+                                Some(self.declare_winner(index, &racers, &buffers[index], output));
+                        }
+                    }
+                },
+                RaceEvent::Done(index, Ok(response)) => match winner {
+                    Some(w) if w == index => return Ok(response),
+                    Some(_) => {}
+                    None if response.contains("COMMAND:")
+                        && parse_response(&response).command().is_some() =>
+                    {
+                        self.declare_winner(index, &racers, &buffers[index], output);
+                        return Ok(response);
+                    }
+                    None => {
+                        if unparsed.is_none() {
+                            unparsed = Some((index, response));
+                        }
+                    }
+                },
+                RaceEvent::Done(index, Err(e)) => {
+                    if winner == Some(index) {
+                        return Err(e);
+                    }
+                    last_error = e;
+                }
+            }
+        }
+
+        match unparsed {
+            Some((index, response)) => {
+                self.declare_winner(index, &racers, &buffers[index], output);
+                Ok(response)
+            }
+            None => Err(last_error),
+        }
+    }
+
+    fn answered_by(&self) -> Option<String> {
+        let winner = (*self.winner.lock().unwrap())?;
+        self.providers.get(winner).map(|(label, _)| label.clone())
+    }
+
+    fn usage(&self) -> Option<Usage> {
+        let winner = (*self.winner.lock().unwrap())?;
+        self.providers.get(winner)?.1.usage()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    /// Streams its chunks with a delay before each one
+    struct StubProvider {
+        delay: Duration,
+        chunks: Vec<&'static str>,
+        result: Result<(), HalpError>,
+    }
+
+    #[async_trait]
+    impl LlmProvider for StubProvider {
+        async fn stream_conversation(
+            &self,
+            _conversation: &Conversation,
+            _system: &str,
+            output: &mut (dyn Write + Send),
+        ) -> Result<String, HalpError> {
+            let mut response = String::new();
+            for chunk in &self.chunks {
+                tokio::time::sleep(self.delay).await;
+                let _ = write!(output, "{}", chunk);
+                response.push_str(chunk);
+            }
+            self.result.clone()?;
+            Ok(response)
+        }
+    }
+
+    fn stub(
+        delay_ms: u64,
+        chunks: Vec<&'static str>,
+        result: Result<(), HalpError>,
+    ) -> (String, Arc<dyn LlmProvider>) {
+        let label = format!("{}ms", delay_ms);
+        let provider = StubProvider {
+            delay: Duration::from_millis(delay_ms),
+            chunks,
+            result,
+        };
+        (label, Arc::new(provider))
+    }
+
+    fn refused() -> HalpError {
+        HalpError::Network("refused".to_string())
+    }
+
+    #[tokio::test]
+    async fn test_fastest_command_wins() {
+        let race = Race::new(vec![
+            stub(50, vec!["COMMAND: slow\n", "EXPLANATION: slow"], Ok(())),
+            stub(1, vec!["COMMAND: fast\n", "EXPLANATION: fast"], Ok(())),
+        ]);
+        let mut output = Vec::new();
+
+        let response = race.stream_completion("q", "s", &mut output).await;
+
+        assert_eq!(response, Ok("COMMAND: fast\nEXPLANATION: fast".to_string()));
+        assert_eq!(race.answered_by(), Some("1ms".to_string()));
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "COMMAND: fast\nEXPLANATION: fast"
+        );
+    }
+
+    /// Sets its flag when dropped, which is when the racer holding it is cancelled
+    struct DropFlag(Arc<AtomicBool>);
+
+    impl Drop for DropFlag {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Never answers in time
+    struct SlowProvider {
+        cancelled: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl LlmProvider for SlowProvider {
+        async fn stream_conversation(
+            &self,
+            _conversation: &Conversation,
+            _system: &str,
+            _output: &mut (dyn Write + Send),
+        ) -> Result<String, HalpError> {
+            let _flag = DropFlag(self.cancelled.clone());
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            Ok("COMMAND: slow\n".to_string())
+        }
+    }
+
+    /// Streams a command, then notes whether the slow racer was cancelled
+    /// before finishing its explanation
+    struct WinningProvider {
+        loser_cancelled: Arc<AtomicBool>,
+        cancelled_before_done: Arc<AtomicBool>,
+    }
+
+    #[async_trait]
+    impl LlmProvider for WinningProvider {
+        async fn stream_conversation(
+            &self,
+            _conversation: &Conversation,
+            _system: &str,
+            output: &mut (dyn Write + Send),
+        ) -> Result<String, HalpError> {
+            let _ = writeln!(output, "COMMAND: fast");
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            self.cancelled_before_done.store(
+                self.loser_cancelled.load(Ordering::SeqCst),
+                Ordering::SeqCst,
+            );
+            let _ = write!(output, "EXPLANATION: fast");
+            Ok("COMMAND: fast\nEXPLANATION: fast".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_losers_are_cancelled_once_winner_is_picked() {
+        let loser_cancelled = Arc::new(AtomicBool::new(false));
+        let cancelled_before_done = Arc::new(AtomicBool::new(false));
+        let race = Race::new(vec![
+            (
+                "slow".to_string(),
+                Arc::new(SlowProvider {
+                    cancelled: loser_cancelled.clone(),
+                }),
+            ),
+            (
+                "fast".to_string(),
+                Arc::new(WinningProvider {
+                    loser_cancelled,
+                    cancelled_before_done: cancelled_before_done.clone(),
+                }),
+            ),
+        ]);
+        let mut output = Vec::new();
+
+        let response = race.stream_completion("q", "s", &mut output).await;
+
+        assert_eq!(response, Ok("COMMAND: fast\nEXPLANATION: fast".to_string()));
+        assert!(cancelled_before_done.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_unparseable_response_does_not_win() {
+        let race = Race::new(vec![
+            stub(1, vec!["I can't help with that\n"], Ok(())),
+            stub(20, vec!["COMMAND: ls\n"], Ok(())),
+        ]);
+        let mut output = Vec::new();
+
+        let response = race.stream_completion("q", "s", &mut output).await;
+
+        assert_eq!(response, Ok("COMMAND: ls\n".to_string()));
+        assert_eq!(race.answered_by(), Some("20ms".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_failures_are_skipped() {
+        let race = Race::new(vec![
+            stub(1, vec![], Err(refused())),
+            stub(10, vec!["COMMAND: pwd"], Ok(())),
+        ]);
+        let mut output = Vec::new();
+
+        let response = race.stream_completion("q", "s", &mut output).await;
+
+        assert_eq!(response, Ok("COMMAND: pwd".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_all_failed_returns_error() {
+        let race = Race::new(vec![
+            stub(1, vec![], Err(refused())),
+            stub(5, vec![], Err(refused())),
+        ]);
+        let mut output = Vec::new();
+
+        let response = race.stream_completion("q", "s", &mut output).await;
+
+        assert_eq!(response, Err(refused()));
+        assert_eq!(race.answered_by(), None);
+    }
+}
@@ -0,0 +1,371 @@
+use crate::config::RetryOptions;
+use reqwest::header::HeaderMap;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// First backoff step; each retry doubles it
+const BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Upper bound for a single computed backoff step
+const MAX_DELAY: Duration = Duration::from_secs(8);
+
+/// Send a request, retrying 429, 503, 529 (Anthropic overloaded) and connection
+/// errors with jittered exponential backoff. Server-provided `Retry-After` and
+/// `anthropic-ratelimit-*-reset` hints take precedence over the backoff.
+///
+/// Once retries or the time budget run out, the last response is returned as-is
+/// so callers keep their usual status handling.
+pub async fn send_with_retry(
+    request: RequestBuilder,
+    options: &RetryOptions,
+) -> Result<Response, reqwest::Error> {
+    send_with_backoff(request, options, BASE_DELAY).await
+}
+
+async fn send_with_backoff(
+    request: RequestBuilder,
+    options: &RetryOptions,
+    base_delay: Duration,
+) -> Result<Response, reqwest::Error> {
+    let started = Instant::now();
+    let mut attempt = 0;
+
+    loop {
+        // Bodies we send are always buffered JSON, so cloning only fails for
+        // streaming bodies; those get a single attempt
+        let Some(this_attempt) = request.try_clone() else {
+            return request.send().await;
+        };
+
+        let result = this_attempt.send().await;
+
+        let delay = match &result {
+            Ok(response) if is_retryable_status(response.status()) => {
+                server_delay(response.headers(), SystemTime::now())
+                    .unwrap_or_else(|| backoff(attempt, base_delay))
+            }
+            Err(e) if e.is_connect() => backoff(attempt, base_delay),
+            _ => return result,
+        };
+
+        if attempt >= options.max_retries || started.elapsed() + delay > options.budget {
+            return result;
+        }
+
+        tokio::time::sleep(delay).await;
+        attempt += 1;
+    }
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(status.as_u16(), 429 | 503 | 529)
+}
+
+/// Full-jitter backoff: a random delay up to the exponential step
+fn backoff(attempt: u32, base_delay: Duration) -> Duration {
+    let step = base_delay
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(MAX_DELAY);
+    let random = RandomState::new().build_hasher().finish();
+    step.mul_f64((random % 1000) as f64 / 1000.0)
+}
+
+/// How long the server asked us to wait, if it said
+fn server_delay(headers: &HeaderMap, now: SystemTime) -> Option<Duration> {
+    retry_after(headers).or_else(|| anthropic_reset(headers, now))
+}
+
+/// `Retry-After` in its delay-seconds form
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get("retry-after")?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|secs| secs.is_finite() && *secs >= 0.0)
+        .map(Duration::from_secs_f64)
+}
+
+/// Latest reset time among the Anthropic rate limits that are used up
+fn anthropic_reset(headers: &HeaderMap, now: SystemTime) -> Option<Duration> {
+    let now = now.duration_since(UNIX_EPOCH).ok()?;
+
+    ["requests", "tokens", "input-tokens", "output-tokens"]
+        .iter()
+        .filter(|limit| {
+            headers
+                .get(format!("anthropic-ratelimit-{}-remaining", limit))
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| v.trim() == "0")
+        })
+        .filter_map(|limit| {
+            let reset = headers
+                .get(format!("anthropic-ratelimit-{}-reset", limit))?
+                .to_str()
+                .ok()?;
+            parse_rfc3339(reset)
+        })
+        .max()
+        .map(|reset| reset.saturating_sub(now))
+}
+
+/// Parse an RFC 3339 timestamp (e.g. `2025-01-01T12:00:30Z`) into time since the epoch
+fn parse_rfc3339(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    let (date, rest) = s.split_once(['T', 't', ' '])?;
+
+    let mut date_parts = date.splitn(3, '-');
+    let year: i64 = date_parts.next()?.parse().ok()?;
+    let month: i64 = date_parts.next()?.parse().ok()?;
+    let day: i64 = date_parts.next()?.parse().ok()?;
+
+    // Split the time from its offset
+    let (time, offset_secs) = if let Some(time) = rest.strip_suffix(['Z', 'z']) {
+        (time, 0)
+    } else {
+        let sign_pos = rest.rfind(['+', '-'])?;
+        let (time, offset) = rest.split_at(sign_pos);
+        let sign = if offset.starts_with('-') { -1 } else { 1 };
+        let (hours, minutes) = offset[1..].split_once(':')?;
+        let offset_secs = hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60;
+        (time, sign * offset_secs)
+    };
+
+    let mut time_parts = time.splitn(3, ':');
+    let hour: i64 = time_parts.next()?.parse().ok()?;
+    let minute: i64 = time_parts.next()?.parse().ok()?;
+    let seconds: f64 = time_parts.next()?.parse().ok()?;
+
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Days since the epoch for a proleptic Gregorian date (Howard Hinnant's algorithm)
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+
+    let secs = days * 86_400 + hour * 3600 + minute * 60 - offset_secs;
+    if secs < 0 {
+        return None;
+    }
+    Some(Duration::from_secs(secs as u64) + Duration::from_secs_f64(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::providers::streaming::create_client;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    /// A minimal HTTP server that answers each request with the next canned
+    /// response. Returns its base URL and a counter of requests served.
+    fn mock_server(responses: Vec<&'static str>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let served = Arc::new(AtomicUsize::new(0));
+        let counter = served.clone();
+
+        thread::spawn(move || {
+            for response in responses {
+                let Ok((stream, _)) = listener.accept() else {
+                    return;
+                };
+                let mut reader = BufReader::new(stream);
+
+                // Read the headers, then the body they announce
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                        break;
+                    }
+                    if let Some(len) = line.to_lowercase().strip_prefix("content-length:") {
+                        content_length = len.trim().parse().unwrap_or(0);
+                    }
+                }
+                let mut body = vec![0; content_length];
+                let _ = reader.read_exact(&mut body);
+
+                counter.fetch_add(1, Ordering::SeqCst);
+                let mut stream = reader.into_inner();
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+
+        (url, served)
+    }
+
+    const OK: &str = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok";
+    const UNAVAILABLE: &str =
+        "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    const OVERLOADED: &str =
+        "HTTP/1.1 529 Overloaded\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    const BAD_REQUEST: &str =
+        "HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    const RATE_LIMITED_NOW: &str = "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 0\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    const RATE_LIMITED_LONG: &str = "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 60\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+    fn options(max_retries: u32) -> RetryOptions {
+        RetryOptions {
+            max_retries,
+            budget: Duration::from_secs(5),
+        }
+    }
+
+    async fn send(url: &str, options: &RetryOptions) -> Result<Response, reqwest::Error> {
+        let request = create_client().post(url).json(&"{}");
+        send_with_backoff(request, options, Duration::from_millis(1)).await
+    }
+
+    #[tokio::test]
+    async fn test_retries_until_success() {
+        let (url, served) = mock_server(vec![UNAVAILABLE, OVERLOADED, OK]);
+
+        let response = send(&url, &options(3)).await.unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.text().await.unwrap(), "ok");
+        assert_eq!(served.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_honors_retry_after() {
+        let (url, served) = mock_server(vec![RATE_LIMITED_NOW, OK]);
+
+        let response = send(&url, &options(1)).await.unwrap();
+
+        assert_eq!(response.status(), 200);
+        assert_eq!(served.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_client_errors() {
+        let (url, served) = mock_server(vec![BAD_REQUEST, OK]);
+
+        let response = send(&url, &options(3)).await.unwrap();
+
+        assert_eq!(response.status(), 400);
+        assert_eq!(served.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_returns_last_response_when_retries_exhausted() {
+        let (url, served) = mock_server(vec![UNAVAILABLE, UNAVAILABLE, UNAVAILABLE]);
+
+        let response = send(&url, &options(2)).await.unwrap();
+
+        assert_eq!(response.status(), 503);
+        assert_eq!(served.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_when_wait_exceeds_budget() {
+        let (url, served) = mock_server(vec![RATE_LIMITED_LONG, OK]);
+
+        let started = Instant::now();
+        let response = send(&url, &options(3)).await.unwrap();
+
+        assert_eq!(response.status(), 429);
+        assert_eq!(served.load(Ordering::SeqCst), 1);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_retries_connection_errors() {
+        // Grab a free port, then close it so connections are refused
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        let result = send(&format!("http://127.0.0.1:{}", port), &options(2)).await;
+
+        assert!(result.unwrap_err().is_connect());
+    }
+
+    #[test]
+    fn test_backoff_stays_within_step() {
+        for attempt in 0..10 {
+            let delay = backoff(attempt, BASE_DELAY);
+            assert!(delay <= MAX_DELAY);
+        }
+        assert!(backoff(0, Duration::from_millis(100)) <= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_parse_rfc3339() {
+        assert_eq!(
+            parse_rfc3339("1970-01-01T00:00:00Z"),
+            Some(Duration::from_secs(0))
+        );
+        assert_eq!(
+            parse_rfc3339("2024-02-29T12:30:15Z"),
+            Some(Duration::from_secs(1_709_209_815))
+        );
+        assert_eq!(
+            parse_rfc3339("2024-02-29T14:30:15+02:00"),
+            Some(Duration::from_secs(1_709_209_815))
+        );
+        assert_eq!(
+            parse_rfc3339("2024-02-29T12:30:15.5Z"),
+            Some(Duration::from_millis(1_709_209_815_500))
+        );
+        assert_eq!(parse_rfc3339("not a date"), None);
+    }
+
+    #[test]
+    fn test_anthropic_reset_uses_exhausted_limits() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "anthropic-ratelimit-requests-remaining",
+            "0".parse().unwrap(),
+        );
+        headers.insert(
+            "anthropic-ratelimit-requests-reset",
+            "2024-02-29T12:30:20Z".parse().unwrap(),
+        );
+        // Tokens are not exhausted, so their later reset is ignored
+        headers.insert(
+            "anthropic-ratelimit-tokens-remaining",
+            "5000".parse().unwrap(),
+        );
+        headers.insert(
+            "anthropic-ratelimit-tokens-reset",
+            "2024-02-29T12:31:00Z".parse().unwrap(),
+        );
+
+        let now = UNIX_EPOCH + Duration::from_secs(1_709_209_815);
+        assert_eq!(server_delay(&headers, now), Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_retry_after_takes_precedence() {
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", "3".parse().unwrap());
+        headers.insert(
+            "anthropic-ratelimit-requests-remaining",
+            "0".parse().unwrap(),
+        );
+        headers.insert(
+            "anthropic-ratelimit-requests-reset",
+            "2024-02-29T12:30:20Z".parse().unwrap(),
+        );
+
+        let now = UNIX_EPOCH + Duration::from_secs(1_709_209_815);
+        assert_eq!(server_delay(&headers, now), Some(Duration::from_secs(3)));
+    }
+}
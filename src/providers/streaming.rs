@@ -1,3 +1,4 @@
+use crate::error::HalpError;
 use std::io::Write;
 use std::time::Duration;
 
@@ -7,9 +8,30 @@ pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
 /// Maximum response size (1MB)
 pub const MAX_RESPONSE_SIZE: usize = 1_048_576;
 
-/// Handles SSE stream processing with buffer management and size limits
+/// Byte order mark that may prefix an event stream
+const BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// A dispatched server-sent event
+#[derive(Debug, Clone, PartialEq)]
+pub struct SseEvent {
+    /// Event type, "message" unless the stream named one
+    pub event: String,
+    pub data: String,
+    /// Last event ID set on the stream, if any
+    pub id: Option<String>,
+}
+
+/// Parses a WHATWG `text/event-stream` with buffer management and size limits.
+/// Bytes are buffered until a full line arrives, so multi-byte characters split
+/// across chunks are decoded intact.
 pub struct SseProcessor {
-    buffer: String,
+    buffer: Vec<u8>,
+    bom_checked: bool,
+    /// The previous chunk ended in `\r`, so a leading `\n` belongs to it
+    skip_lf: bool,
+    event_type: String,
+    data: String,
+    last_event_id: Option<String>,
     full_response: String,
     max_size: usize,
 }
@@ -17,7 +39,12 @@ pub struct SseProcessor {
 impl SseProcessor {
     pub fn new() -> Self {
         Self {
-            buffer: String::new(),
+            buffer: Vec::new(),
+            bom_checked: false,
+            skip_lf: false,
+            event_type: String::new(),
+            data: String::new(),
+            last_event_id: None,
             full_response: String::new(),
             max_size: MAX_RESPONSE_SIZE,
         }
@@ -30,36 +57,124 @@ impl SseProcessor {
 
     /// Append a chunk to the buffer
     pub fn push_chunk(&mut self, chunk: &[u8]) {
-        self.buffer.push_str(&String::from_utf8_lossy(chunk));
+        self.buffer.extend_from_slice(chunk);
+
+        if !self.bom_checked {
+            if self.buffer.starts_with(BOM) {
+                self.buffer.drain(..BOM.len());
+                self.bom_checked = true;
+            } else if !BOM.starts_with(&self.buffer) {
+                self.bom_checked = true;
+            }
+        }
+    }
+
+    /// Parse the next complete event from the buffer, if there is one
+    pub fn next_event(&mut self) -> Option<SseEvent> {
+        while let Some(line) = self.next_line() {
+            if line.is_empty() {
+                if let Some(event) = self.dispatch() {
+                    return Some(event);
+                }
+            } else {
+                self.process_line(&line);
+            }
+        }
+        None
+    }
+
+    /// Take the next line terminated by `\r\n`, `\n` or `\r`
+    fn next_line(&mut self) -> Option<String> {
+        if self.skip_lf && !self.buffer.is_empty() {
+            if self.buffer[0] == b'\n' {
+                self.buffer.remove(0);
+            }
+            self.skip_lf = false;
+        }
+
+        let end = self.buffer.iter().position(|&b| b == b'\n' || b == b'\r')?;
+        let terminator_len = match (self.buffer[end], self.buffer.get(end + 1)) {
+            (b'\r', Some(b'\n')) => 2,
+            (b'\r', None) => {
+                self.skip_lf = true;
+                1
+            }
+            _ => 1,
+        };
+
+        let line: Vec<u8> = self
+            .buffer
+            .drain(..end + terminator_len)
+            .take(end)
+            .collect();
+        Some(String::from_utf8_lossy(&line).into_owned())
+    }
+
+    fn process_line(&mut self, line: &str) {
+        // Comment
+        if line.starts_with(':') {
+            return;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+
+        match field {
+            "event" => self.event_type = value.to_string(),
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            }
+            "id" if !value.contains('\0') => self.last_event_id = Some(value.to_string()),
+            // `retry` only sets the reconnection time, and a response stream is
+            // never reconnected
+            _ => {}
+        }
+    }
+
+    /// Finish the event being assembled on a blank line
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event_type = std::mem::take(&mut self.event_type);
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let mut data = std::mem::take(&mut self.data);
+        if data.ends_with('\n') {
+            data.pop();
+        }
+
+        Some(SseEvent {
+            event: if event_type.is_empty() {
+                "message".to_string()
+            } else {
+                event_type
+            },
+            data,
+            id: self.last_event_id.clone(),
+        })
     }
 
     /// Process complete SSE events from the buffer.
-    /// Calls the provided closure for each "data: " line (excluding [DONE]).
+    /// Calls the provided closure for each event (excluding `[DONE]` markers).
     /// Returns Err if the closure returns an error or if response size exceeds limit.
-    pub fn process_events<F>(&mut self, mut handler: F) -> Result<(), String>
+    pub fn process_events<F>(&mut self, mut handler: F) -> Result<(), HalpError>
     where
-        F: FnMut(&str) -> Result<Option<String>, String>,
+        F: FnMut(&SseEvent) -> Result<Option<String>, HalpError>,
     {
-        while let Some(event_end) = self.buffer.find("\n\n") {
-            let event_data: String = self.buffer.drain(..event_end + 2).collect();
-
-            for line in event_data.lines() {
-                if let Some(data) = line.strip_prefix("data: ") {
-                    if data == "[DONE]" {
-                        continue;
-                    }
-
-                    if let Some(text) = handler(data)? {
-                        self.full_response.push_str(&text);
-
-                        // Check size limit
-                        if self.full_response.len() > self.max_size {
-                            return Err(format!(
-                                "Response too large (>{} bytes)",
-                                self.max_size
-                            ));
-                        }
-                    }
+        while let Some(event) = self.next_event() {
+            if event.data == "[DONE]" {
+                continue;
+            }
+
+            if let Some(text) = handler(&event)? {
+                self.full_response.push_str(&text);
+
+                // Check size limit
+                if self.full_response.len() > self.max_size {
+                    return Err(HalpError::SizeLimit(self.max_size));
                 }
             }
         }
@@ -71,9 +186,9 @@ impl SseProcessor {
         &mut self,
         output: &mut (dyn Write + Send),
         handler: F,
-    ) -> Result<(), String>
+    ) -> Result<(), HalpError>
     where
-        F: FnMut(&str) -> Result<Option<String>, String>,
+        F: FnMut(&SseEvent) -> Result<Option<String>, HalpError>,
     {
         let response_before = self.full_response.len();
         self.process_events(handler)?;
@@ -94,6 +209,123 @@ impl Default for SseProcessor {
     }
 }
 
+/// Handles newline-delimited JSON streams (e.g. Ollama) with the same size limits
+pub struct NdjsonProcessor {
+    buffer: Vec<u8>,
+    full_response: String,
+    max_size: usize,
+}
+
+impl NdjsonProcessor {
+    pub fn new() -> Self {
+        Self {
+            buffer: Vec::new(),
+            full_response: String::new(),
+            max_size: MAX_RESPONSE_SIZE,
+        }
+    }
+
+    /// Returns the accumulated full response
+    pub fn into_response(self) -> String {
+        self.full_response
+    }
+
+    /// Append a chunk to the buffer
+    pub fn push_chunk(&mut self, chunk: &[u8]) {
+        self.buffer.extend_from_slice(chunk);
+    }
+
+    /// Process complete lines from the buffer.
+    /// Calls the provided closure for each non-empty line.
+    /// Returns Err if the closure returns an error or if response size exceeds limit.
+    pub fn process_lines<F>(&mut self, mut handler: F) -> Result<(), HalpError>
+    where
+        F: FnMut(&str) -> Result<Option<String>, HalpError>,
+    {
+        while let Some(line_end) = self.buffer.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=line_end).collect();
+            self.handle_line(&line, &mut handler)?;
+        }
+        Ok(())
+    }
+
+    /// Process a trailing line that was not terminated by a newline
+    pub fn finish<F>(&mut self, mut handler: F) -> Result<(), HalpError>
+    where
+        F: FnMut(&str) -> Result<Option<String>, HalpError>,
+    {
+        self.process_lines(&mut handler)?;
+        let line = std::mem::take(&mut self.buffer);
+        self.handle_line(&line, &mut handler)
+    }
+
+    fn handle_line<F>(&mut self, line: &[u8], handler: &mut F) -> Result<(), HalpError>
+    where
+        F: FnMut(&str) -> Result<Option<String>, HalpError>,
+    {
+        // Decoding whole lines keeps multi-byte characters split across chunks intact
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(());
+        }
+
+        if let Some(text) = handler(line)? {
+            self.full_response.push_str(&text);
+
+            // Check size limit
+            if self.full_response.len() > self.max_size {
+                return Err(HalpError::SizeLimit(self.max_size));
+            }
+        }
+        Ok(())
+    }
+
+    /// Convenience method to process lines and write text to output
+    pub fn process_lines_with_output<F>(
+        &mut self,
+        output: &mut (dyn Write + Send),
+        handler: F,
+    ) -> Result<(), HalpError>
+    where
+        F: FnMut(&str) -> Result<Option<String>, HalpError>,
+    {
+        let response_before = self.full_response.len();
+        self.process_lines(handler)?;
+        self.write_new_text(output, response_before);
+        Ok(())
+    }
+
+    /// Convenience method to finish the stream and write text to output
+    pub fn finish_with_output<F>(
+        &mut self,
+        output: &mut (dyn Write + Send),
+        handler: F,
+    ) -> Result<(), HalpError>
+    where
+        F: FnMut(&str) -> Result<Option<String>, HalpError>,
+    {
+        let response_before = self.full_response.len();
+        self.finish(handler)?;
+        self.write_new_text(output, response_before);
+        Ok(())
+    }
+
+    fn write_new_text(&self, output: &mut (dyn Write + Send), response_before: usize) {
+        if self.full_response.len() > response_before {
+            let new_text = &self.full_response[response_before..];
+            let _ = write!(output, "{}", new_text);
+            let _ = output.flush();
+        }
+    }
+}
+
+impl Default for NdjsonProcessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Create a reqwest client with default timeout
 pub fn create_client() -> reqwest::Client {
     reqwest::Client::builder()
@@ -113,8 +345,8 @@ mod tests {
 
         let mut received = Vec::new();
         processor
-            .process_events(|data| {
-                received.push(data.to_string());
+            .process_events(|event| {
+                received.push(event.data.clone());
                 Ok(Some("hello".to_string()))
             })
             .unwrap();
@@ -190,8 +422,7 @@ mod tests {
         processor.push_chunk(b"data: test\n\n");
         let result = processor.process_events(|_| Ok(Some("this is too long".to_string())));
 
-        assert!(result.is_err());
-        assert!(result.unwrap_err().contains("too large"));
+        assert_eq!(result, Err(HalpError::SizeLimit(10)));
     }
 
     #[test]
@@ -199,9 +430,182 @@ mod tests {
         let mut processor = SseProcessor::new();
         processor.push_chunk(b"data: test\n\n");
 
-        let result = processor.process_events(|_| Err("parse error".to_string()));
+        let result = processor.process_events(|_| Err(HalpError::Parse("parse error".to_string())));
+
+        assert_eq!(result, Err(HalpError::Parse("parse error".to_string())));
+    }
+
+    fn collect_events(processor: &mut SseProcessor) -> Vec<SseEvent> {
+        std::iter::from_fn(|| processor.next_event()).collect()
+    }
+
+    fn event(data: &str) -> SseEvent {
+        SseEvent {
+            event: "message".to_string(),
+            data: data.to_string(),
+            id: None,
+        }
+    }
+
+    #[test]
+    fn test_crlf_and_cr_line_endings() {
+        let mut processor = SseProcessor::new();
+        processor.push_chunk(b"data: crlf\r\n\r\ndata: cr\r\rdata: lf\n\n");
+
+        assert_eq!(
+            collect_events(&mut processor),
+            vec![event("crlf"), event("cr"), event("lf")]
+        );
+    }
+
+    #[test]
+    fn test_crlf_split_across_chunks() {
+        let mut processor = SseProcessor::new();
+        processor.push_chunk(b"data: one\r");
+        processor.push_chunk(b"\n\r");
+        processor.push_chunk(b"\ndata: two\r\n\r\n");
+
+        assert_eq!(
+            collect_events(&mut processor),
+            vec![event("one"), event("two")]
+        );
+    }
+
+    #[test]
+    fn test_multiline_data() {
+        let mut processor = SseProcessor::new();
+        processor.push_chunk(b"data: first\ndata:second\ndata\n\n");
+
+        assert_eq!(
+            collect_events(&mut processor),
+            vec![event("first\nsecond\n")]
+        );
+    }
+
+    #[test]
+    fn test_event_id_field() {
+        let mut processor = SseProcessor::new();
+        processor.push_chunk(b"event: content_block_delta\nid: 7\nretry: 1500\ndata: {}\n\n");
+        processor.push_chunk(b"data: next\n\n");
+
+        assert_eq!(
+            collect_events(&mut processor),
+            vec![
+                SseEvent {
+                    event: "content_block_delta".to_string(),
+                    data: "{}".to_string(),
+                    id: Some("7".to_string()),
+                },
+                SseEvent {
+                    event: "message".to_string(),
+                    data: "next".to_string(),
+                    id: Some("7".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_comments_and_empty_events_ignored() {
+        let mut processor = SseProcessor::new();
+        processor.push_chunk(b": keep-alive\n\nevent: ping\n\nretry: soon\ndata: x\n\n");
+
+        assert_eq!(collect_events(&mut processor), vec![event("x")]);
+    }
+
+    #[test]
+    fn test_multibyte_character_split_across_chunks() {
+        let mut processor = SseProcessor::new();
+        let stream = "data: ✓ done\n\n".as_bytes();
+        // '✓' is three bytes starting at offset 6
+        processor.push_chunk(&stream[..7]);
+        processor.push_chunk(&stream[7..]);
+
+        assert_eq!(collect_events(&mut processor), vec![event("✓ done")]);
+    }
+
+    #[test]
+    fn test_leading_bom_stripped() {
+        let mut processor = SseProcessor::new();
+        processor.push_chunk(b"\xEF\xBB");
+        processor.push_chunk(b"\xBFdata: x\n\n");
+
+        assert_eq!(collect_events(&mut processor), vec![event("x")]);
+    }
+
+    #[test]
+    fn test_ndjson_process_lines() {
+        let mut processor = NdjsonProcessor::new();
+        processor.push_chunk(b"{\"a\":1}\n{\"a\":2}\n");
+
+        let mut received = Vec::new();
+        processor
+            .process_lines(|line| {
+                received.push(line.to_string());
+                Ok(Some("x".to_string()))
+            })
+            .unwrap();
+
+        assert_eq!(received, vec!["{\"a\":1}", "{\"a\":2}"]);
+        assert_eq!(processor.into_response(), "xx");
+    }
+
+    #[test]
+    fn test_ndjson_incomplete_line_buffered() {
+        let mut processor = NdjsonProcessor::new();
+        processor.push_chunk(b"{\"a\":");
+
+        let mut count = 0;
+        processor
+            .process_lines(|_| {
+                count += 1;
+                Ok(None)
+            })
+            .unwrap();
+        assert_eq!(count, 0);
+
+        processor.push_chunk(b"1}\n");
+        processor
+            .process_lines(|_| {
+                count += 1;
+                Ok(None)
+            })
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_ndjson_split_multibyte_character() {
+        let mut processor = NdjsonProcessor::new();
+        let line = "{\"text\":\"héllo\"}\n".as_bytes();
+        // Split inside the two-byte 'é'
+        processor.push_chunk(&line[..11]);
+        processor.push_chunk(&line[11..]);
+
+        let mut received = Vec::new();
+        processor
+            .process_lines(|line| {
+                received.push(line.to_string());
+                Ok(None)
+            })
+            .unwrap();
+
+        assert_eq!(received, vec!["{\"text\":\"héllo\"}"]);
+    }
+
+    #[test]
+    fn test_ndjson_finish_handles_trailing_line() {
+        let mut processor = NdjsonProcessor::new();
+        processor.push_chunk(b"\r\n\nlast");
+
+        let mut received = Vec::new();
+        processor
+            .finish(|line| {
+                received.push(line.to_string());
+                Ok(None)
+            })
+            .unwrap();
 
-        assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), "parse error");
+        assert_eq!(received, vec!["last"]);
     }
 }
@@ -0,0 +1,275 @@
+use crate::output::{BOLD, RESET};
+use crate::prompt::StdinContext;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+
+/// The controlling terminal, which is still there when stdin is piped
+/// and stdout is captured
+#[cfg(unix)]
+const TTY: &str = "/dev/tty";
+
+/// Whether commands can be confirmed and run here, which needs a Unix
+/// terminal (`/dev/tty`) and shell
+pub const SUPPORTED: bool = cfg!(unix);
+
+/// Open the controlling terminal to ask the user something
+pub(crate) fn open_tty() -> io::Result<File> {
+    #[cfg(unix)]
+    return OpenOptions::new().read(true).write(true).open(TTY);
+
+    #[cfg(not(unix))]
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "asking on the terminal needs /dev/tty, which this platform doesn't have",
+    ))
+}
+
+/// An answer to the `[y/N/e(dit)]` prompt
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Answer {
+    Yes,
+    No,
+    Edit,
+}
+
+fn parse_answer(input: &str) -> Answer {
+    match input.trim().to_lowercase().as_str() {
+        "y" | "yes" => Answer::Yes,
+        "e" | "edit" => Answer::Edit,
+        _ => Answer::No,
+    }
+}
+
+/// Show the command on the terminal and ask whether to run it, letting the
+/// user edit it in `$VISUAL`/`$EDITOR` first. Returns the command to run, or
+/// `None` if the user declined.
+pub fn confirm(command: &str) -> io::Result<Option<String>> {
+    let mut tty = open_tty()?;
+    let mut reader = BufReader::new(tty.try_clone()?);
+    let mut command = command.to_string();
+
+    loop {
+        write!(
+            tty,
+            "{}{}{}\nRun this command? [y/N/e(dit)] ",
+            BOLD, command, RESET
+        )?;
+        tty.flush()?;
+
+        let mut input = String::new();
+        if reader.read_line(&mut input)? == 0 {
+            // EOF (Ctrl-D) declines
+            writeln!(tty)?;
+            return Ok(None);
+        }
+        match parse_answer(&input) {
+            Answer::Yes => return Ok(Some(command)),
+            Answer::No => return Ok(None),
+            Answer::Edit => {
+                command = edit(&command)?;
+                if command.is_empty() {
+                    return Ok(None);
+                }
+            }
+        }
+    }
+}
+
+/// Open the command in the user's editor and return what they saved
+fn edit(command: &str) -> io::Result<String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let path = create_temp_file(&env::temp_dir(), &format!("{}\n", command))?;
+
+    // Through sh, since editors are often configured with arguments (`code --wait`)
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(&path)
+        .stdin(Stdio::from(open_tty()?))
+        .status();
+    let edited = fs::read_to_string(&path);
+    let _ = fs::remove_file(&path);
+
+    if !status?.success() {
+        return Err(io::Error::other(format!("{} exited with an error", editor)));
+    }
+    Ok(edited?.trim().to_string())
+}
+
+/// Write `content` to a new file in `dir`, readable only by the user. It's
+/// always created new, so a file or symlink that someone else put at the
+/// path in a shared temp directory is never written through.
+fn create_temp_file(dir: &Path, content: &str) -> io::Result<PathBuf> {
+    let mut attempt = 0;
+    loop {
+        let path = dir.join(format!("halp-{}-{}.sh", std::process::id(), attempt));
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        match options.open(&path) {
+            Ok(mut file) => {
+                file.write_all(content.as_bytes())?;
+                return Ok(path);
+            }
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Run the command with the user's shell (`$SHELL`, or `sh`), with output
+/// going straight to the terminal
+pub fn run_in_shell(command: &str) -> io::Result<ExitStatus> {
+    run_with(&user_shell(), command)
+}
+
+fn user_shell() -> String {
+    env::var("SHELL")
+        .ok()
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "sh".to_string())
+}
+
+fn run_with(shell: &str, command: &str) -> io::Result<ExitStatus> {
+    Command::new(shell).arg("-c").arg(command).status()
+}
+
+/// Like [`run_in_shell`], but also keep the last `limit` bytes of the
+/// command's stderr while passing it through, to send back when it fails
+pub fn run_capturing_stderr(
+    command: &str,
+    limit: usize,
+) -> io::Result<(ExitStatus, Option<StdinContext>)> {
+    let shell = user_shell();
+    capture_with(&shell, command, limit)
+}
+
+fn capture_with(
+    shell: &str,
+    command: &str,
+    limit: usize,
+) -> io::Result<(ExitStatus, Option<StdinContext>)> {
+    let mut child = Command::new(shell)
+        .arg("-c")
+        .arg(command)
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let mut pipe = child.stderr.take().expect("stderr is piped");
+    let mut captured = Vec::new();
+    let mut truncated = false;
+    let mut buf = [0; 8192];
+    loop {
+        let n = match pipe.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        let _ = io::stderr().write_all(&buf[..n]);
+        captured.extend_from_slice(&buf[..n]);
+        // Errors tend to be at the end, so keep the tail
+        if captured.len() > limit {
+            captured.drain(..captured.len() - limit);
+            truncated = true;
+        }
+    }
+    let status = child.wait()?;
+
+    let content = String::from_utf8_lossy(&captured).into_owned();
+    let stderr = (!content.is_empty()).then_some(StdinContext { content, truncated });
+    Ok((status, stderr))
+}
+
+/// The exit code to pass on for a command's status, using the shell's
+/// 128 + signal convention for commands killed by a signal
+pub fn exit_code(status: ExitStatus) -> u8 {
+    if let Some(code) = status.code() {
+        return code as u8;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128u8.wrapping_add(signal as u8);
+        }
+    }
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_answer() {
+        assert_eq!(parse_answer("y\n"), Answer::Yes);
+        assert_eq!(parse_answer(" YES "), Answer::Yes);
+        assert_eq!(parse_answer("e\n"), Answer::Edit);
+        assert_eq!(parse_answer("\n"), Answer::No);
+        assert_eq!(parse_answer("n\n"), Answer::No);
+        assert_eq!(parse_answer("sure\n"), Answer::No);
+    }
+
+    #[test]
+    fn test_create_temp_file_never_reuses_a_path() {
+        let dir = env::temp_dir().join(format!("halp-run-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("target");
+        fs::write(&target, "keep").unwrap();
+        let taken = dir.join(format!("halp-{}-0.sh", std::process::id()));
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&target, &taken).unwrap();
+        #[cfg(not(unix))]
+        fs::write(&taken, "keep").unwrap();
+
+        let path = create_temp_file(&dir, "ls -la\n").unwrap();
+        assert_ne!(path, taken);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "ls -la\n");
+        assert_eq!(fs::read_to_string(&target).unwrap(), "keep");
+
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let status = run_with("sh", "exit 3").unwrap();
+        assert_eq!(exit_code(status), 3);
+
+        let status = run_with("sh", "true && false").unwrap();
+        assert_eq!(exit_code(status), 1);
+
+        #[cfg(unix)]
+        {
+            let status = run_with("sh", "kill -TERM $$").unwrap();
+            assert_eq!(exit_code(status), 128 + 15);
+        }
+    }
+
+    #[test]
+    fn test_capture_keeps_tail_of_stderr() {
+        let (status, stderr) = capture_with("sh", "echo one >&2; echo two >&2; exit 2", 4).unwrap();
+        assert_eq!(exit_code(status), 2);
+        assert_eq!(
+            stderr,
+            Some(StdinContext {
+                content: "two\n".to_string(),
+                truncated: true,
+            })
+        );
+
+        let (status, stderr) = capture_with("sh", "true", 100).unwrap();
+        assert!(status.success());
+        assert_eq!(stderr, None);
+    }
+}
@@ -0,0 +1,854 @@
+use serde::Serialize;
+use std::fmt;
+
+/// How much damage a command could do if run by mistake
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Risk {
+    Low,
+    Medium,
+    High,
+}
+
+impl fmt::Display for Risk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Risk::Low => write!(f, "low"),
+            Risk::Medium => write!(f, "medium"),
+            Risk::High => write!(f, "high"),
+        }
+    }
+}
+
+/// Something potentially destructive in a command
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Finding {
+    pub risk: Risk,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({} risk)", self.message, self.risk)
+    }
+}
+
+/// Paths that are too broad to delete or chmod recursively
+const BROAD_PATHS: &[&str] = &[
+    "/",
+    "~",
+    "$HOME",
+    "${HOME}",
+    ".",
+    "..",
+    "*",
+    "/bin",
+    "/boot",
+    "/dev",
+    "/etc",
+    "/home",
+    "/lib",
+    "/lib64",
+    "/opt",
+    "/proc",
+    "/root",
+    "/sbin",
+    "/srv",
+    "/sys",
+    "/usr",
+    "/var",
+    "/Applications",
+    "/Library",
+    "/System",
+    "/Users",
+    "/Volumes",
+];
+
+/// Block devices that writing to would destroy a filesystem
+const DISK_DEVICES: &[&str] = &[
+    "/dev/sd",
+    "/dev/hd",
+    "/dev/vd",
+    "/dev/xvd",
+    "/dev/nvme",
+    "/dev/mmcblk",
+    "/dev/disk",
+    "/dev/rdisk",
+    "/dev/mapper/",
+];
+
+/// Programs that run the script they're fed on stdin
+const INTERPRETERS: &[&str] = &[
+    "sh", "bash", "zsh", "dash", "ksh", "fish", "python", "python3", "perl", "ruby", "node",
+];
+
+/// Prefixes that run the command after them
+const WRAPPERS: &[&str] = &[
+    "sudo", "doas", "env", "nohup", "time", "nice", "command", "exec", "xargs",
+];
+
+/// Flag destructive patterns in a shell command, highest risk first
+pub fn analyze(command: &str) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let parsed = parse(command);
+
+    for pipeline in &parsed.pipelines {
+        check_pipeline(pipeline, &mut findings);
+        for stage in pipeline {
+            check_command(stage, &mut findings);
+        }
+    }
+    for body in &parsed.substitutions {
+        findings.extend(analyze(body));
+    }
+    check_text(command, &mut findings);
+
+    findings.sort_by_key(|f| std::cmp::Reverse(f.risk));
+    findings.dedup();
+    findings
+}
+
+/// One command in a pipeline, e.g. `rm -rf build` in `make clean && rm -rf build`
+#[derive(Debug, Default, PartialEq)]
+struct SimpleCommand {
+    words: Vec<String>,
+    /// Targets of output redirections (`>`, `>>`)
+    redirects: Vec<String>,
+}
+
+/// Commands with `|` between them
+type Pipeline = Vec<SimpleCommand>;
+
+#[derive(Debug, Default)]
+struct ParsedCommand {
+    pipelines: Vec<Pipeline>,
+    /// Bodies of `$(...)`, `<(...)` and backticks, which run as commands of their own
+    substitutions: Vec<String>,
+}
+
+/// Split a command line into pipelines of simple commands. This isn't a full
+/// shell parser: it knows quoting, escapes, comments, substitutions, heredocs
+/// and the operators that separate commands, which is enough to find the
+/// program and arguments of each command.
+fn parse(command: &str) -> ParsedCommand {
+    let mut state = ParseState::default();
+    let chars: Vec<char> = command.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\\' => {
+                // A backslash-newline is a line continuation
+                if chars.get(i + 1) == Some(&'\n') {
+                    i += 2;
+                    continue;
+                }
+                if let Some(&next) = chars.get(i + 1) {
+                    state.word.push(next);
+                }
+                state.in_word = true;
+                i += 2;
+                continue;
+            }
+            '\'' => {
+                state.in_word = true;
+                i += 1;
+                while i < chars.len() && chars[i] != '\'' {
+                    state.word.push(chars[i]);
+                    i += 1;
+                }
+            }
+            '"' => {
+                state.in_word = true;
+                i += 1;
+                while i < chars.len() && chars[i] != '"' {
+                    if chars[i] == '\\' && i + 1 < chars.len() {
+                        i += 1;
+                    } else if chars[i] == '$' && chars.get(i + 1) == Some(&'(') {
+                        let (body, end) = read_parenthesized(&chars, i + 2);
+                        state.word.push_str("$(");
+                        state.word.push_str(&body);
+                        state.word.push(')');
+                        state.parsed.substitutions.push(body);
+                        i = end;
+                        continue;
+                    }
+                    state.word.push(chars[i]);
+                    i += 1;
+                }
+            }
+            '$' | '<' if chars.get(i + 1) == Some(&'(') => {
+                let (body, end) = read_parenthesized(&chars, i + 2);
+                state.word.push(c);
+                state.word.push('(');
+                state.word.push_str(&body);
+                state.word.push(')');
+                state.parsed.substitutions.push(body);
+                state.in_word = true;
+                i = end;
+                continue;
+            }
+            '`' => {
+                let start = i + 1;
+                let end = chars[start..]
+                    .iter()
+                    .position(|&ch| ch == '`')
+                    .map(|p| start + p)
+                    .unwrap_or(chars.len());
+                let body: String = chars[start..end].iter().collect();
+                state.word.push('`');
+                state.word.push_str(&body);
+                state.word.push('`');
+                state.parsed.substitutions.push(body);
+                state.in_word = true;
+                i = end;
+            }
+            '#' if !state.in_word => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            ' ' | '\t' => {
+                state.end_word();
+            }
+            '\n' => {
+                state.end_pipeline();
+                // Skip heredoc bodies, which are data rather than commands
+                for delimiter in std::mem::take(&mut state.heredocs) {
+                    i += 1;
+                    while i < chars.len() {
+                        let line_end = chars[i..]
+                            .iter()
+                            .position(|&ch| ch == '\n')
+                            .map(|p| i + p)
+                            .unwrap_or(chars.len());
+                        let line: String = chars[i..line_end].iter().collect();
+                        i = line_end;
+                        if line.trim() == delimiter {
+                            break;
+                        }
+                        i += 1;
+                    }
+                }
+            }
+            ';' => {
+                state.end_pipeline();
+            }
+            '|' => {
+                if chars.get(i + 1) == Some(&'|') {
+                    state.end_pipeline();
+                    i += 1;
+                } else {
+                    state.end_command();
+                    // `|&` pipes stderr too
+                    if chars.get(i + 1) == Some(&'&') {
+                        i += 1;
+                    }
+                }
+            }
+            '&' => {
+                if chars.get(i + 1) == Some(&'>') {
+                    // `&>file` redirects both streams
+                    state.end_word();
+                    state.redirect_next = true;
+                    i += 1;
+                } else {
+                    state.end_pipeline();
+                    if chars.get(i + 1) == Some(&'&') {
+                        i += 1;
+                    }
+                }
+            }
+            '>' => {
+                state.end_word();
+                match chars.get(i + 1) {
+                    Some('>') | Some('|') => i += 1,
+                    Some('&') => {
+                        // `2>&1` duplicates a descriptor rather than naming a file
+                        i += 2;
+                        while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '-') {
+                            i += 1;
+                        }
+                        continue;
+                    }
+                    _ => {}
+                }
+                state.redirect_next = true;
+            }
+            '<' => {
+                state.end_word();
+                if chars.get(i + 1) == Some(&'<') {
+                    if chars.get(i + 2) == Some(&'<') {
+                        // A here-string's word is data
+                        i += 2;
+                        state.redirect_next = true;
+                    } else {
+                        i += 1;
+                        if chars.get(i + 1) == Some(&'-') {
+                            i += 1;
+                        }
+                        state.heredoc_next = true;
+                    }
+                } else {
+                    // Input redirection: the file isn't an argument
+                    state.redirect_next = true;
+                }
+            }
+            '(' | ')' | '{' | '}' if !state.in_word => {
+                state.end_command();
+            }
+            _ => {
+                state.word.push(c);
+                state.in_word = true;
+            }
+        }
+        i += 1;
+    }
+    state.end_pipeline();
+
+    state.parsed
+}
+
+/// What [`parse`] has read so far
+#[derive(Default)]
+struct ParseState {
+    parsed: ParsedCommand,
+    pipeline: Pipeline,
+    current: SimpleCommand,
+    word: String,
+    in_word: bool,
+    /// The next word is the target of a redirection
+    redirect_next: bool,
+    /// The next word is a heredoc delimiter
+    heredoc_next: bool,
+    /// Delimiters of heredocs whose bodies start after the current line
+    heredocs: Vec<String>,
+}
+
+impl ParseState {
+    fn end_word(&mut self) {
+        if !self.in_word {
+            return;
+        }
+        let word = std::mem::take(&mut self.word);
+        if self.heredoc_next {
+            self.heredocs
+                .push(word.trim_matches(['\'', '"']).to_string());
+            self.heredoc_next = false;
+        } else if self.redirect_next {
+            self.current.redirects.push(word);
+            self.redirect_next = false;
+        } else {
+            self.current.words.push(word);
+        }
+        self.in_word = false;
+    }
+
+    fn end_command(&mut self) {
+        self.end_word();
+        if !self.current.words.is_empty() || !self.current.redirects.is_empty() {
+            self.pipeline.push(std::mem::take(&mut self.current));
+        }
+    }
+
+    fn end_pipeline(&mut self) {
+        self.end_command();
+        if !self.pipeline.is_empty() {
+            self.parsed
+                .pipelines
+                .push(std::mem::take(&mut self.pipeline));
+        }
+    }
+}
+
+/// Read up to the parenthesis matching one already opened; `start` is just past it.
+/// Returns the text between them and the index just past the closing one.
+fn read_parenthesized(chars: &[char], start: usize) -> (String, usize) {
+    let mut depth = 1;
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return (chars[start..i].iter().collect(), i + 1);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    (chars[start..].iter().collect(), chars.len())
+}
+
+/// The program's name and its arguments, looking past `sudo`, `env`,
+/// variable assignments and the like
+fn program_and_args(words: &[String]) -> Option<(&str, &[String])> {
+    let (program, args) = strip_wrappers(words).split_first()?;
+    let name = program.rsplit('/').next().unwrap_or(program);
+    Some((name, args))
+}
+
+/// The words of a command from its program on
+fn strip_wrappers(words: &[String]) -> &[String] {
+    let mut i = 0;
+    while i < words.len() {
+        let word = words[i].as_str();
+        let name = word.rsplit('/').next().unwrap_or(word);
+        if is_assignment(word) {
+            i += 1;
+        } else if WRAPPERS.contains(&name) {
+            i += 1;
+            // Skip the wrapper's own options, and the values of the common ones that take one
+            while i < words.len() && (words[i].starts_with('-') || is_assignment(&words[i])) {
+                let takes_value = matches!(words[i].as_str(), "-u" | "-g" | "-n" | "-I" | "-P");
+                i += if takes_value { 2 } else { 1 };
+            }
+        } else {
+            break;
+        }
+    }
+    &words[i.min(words.len())..]
+}
+
+/// Every simple command in a command line, including those in substitutions,
+/// as its words from the program on
+pub(crate) fn simple_commands(command: &str) -> Vec<Vec<String>> {
+    let parsed = parse(command);
+    let mut commands: Vec<Vec<String>> = parsed
+        .pipelines
+        .iter()
+        .flatten()
+        .map(|c| strip_wrappers(&c.words).to_vec())
+        .filter(|words| !words.is_empty())
+        .collect();
+    for body in &parsed.substitutions {
+        commands.extend(simple_commands(body));
+    }
+    commands
+}
+
+fn is_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => {
+            !name.is_empty()
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+/// Whether an argument is a short option cluster (`-rf`) containing `flag`
+fn has_short_flag(args: &[String], flag: char) -> bool {
+    args.iter()
+        .any(|arg| arg.starts_with('-') && !arg.starts_with("--") && arg[1..].contains(flag))
+}
+
+fn has_flag(args: &[String], long: &str) -> bool {
+    args.iter().any(|arg| arg == long)
+}
+
+fn is_broad_path(path: &str) -> bool {
+    let trimmed = path.trim_end_matches('*').trim_end_matches('/');
+    let trimmed = if trimmed.is_empty() && path.starts_with('/') {
+        "/"
+    } else {
+        trimmed
+    };
+    let trimmed = trimmed.strip_suffix("/.").unwrap_or(trimmed);
+    BROAD_PATHS.contains(&trimmed) || BROAD_PATHS.contains(&path)
+}
+
+fn is_disk_device(path: &str) -> bool {
+    DISK_DEVICES.iter().any(|device| path.starts_with(device))
+}
+
+/// Arguments that aren't options
+fn operands(args: &[String]) -> impl Iterator<Item = &String> {
+    args.iter().filter(|arg| !arg.starts_with('-'))
+}
+
+fn check_pipeline(pipeline: &Pipeline, findings: &mut Vec<Finding>) {
+    let mut downloading = false;
+    for stage in pipeline {
+        let Some((program, _)) = program_and_args(&stage.words) else {
+            continue;
+        };
+        if downloading && INTERPRETERS.contains(&program) {
+            findings.push(Finding {
+                risk: Risk::High,
+                message: format!(
+                    "pipes a download straight into {}, running whatever the server sends",
+                    program
+                ),
+            });
+        }
+        if matches!(program, "curl" | "wget" | "fetch") {
+            downloading = true;
+        }
+    }
+}
+
+fn check_command(command: &SimpleCommand, findings: &mut Vec<Finding>) {
+    let mut flag = |risk: Risk, message: String| findings.push(Finding { risk, message });
+
+    for target in &command.redirects {
+        if is_disk_device(target) {
+            flag(Risk::High, format!("overwrites the disk device {}", target));
+        }
+    }
+
+    let Some((program, args)) = program_and_args(&command.words) else {
+        return;
+    };
+
+    match program {
+        "rm" => {
+            let recursive = has_short_flag(args, 'r')
+                || has_short_flag(args, 'R')
+                || has_flag(args, "--recursive");
+            let force = has_short_flag(args, 'f') || has_flag(args, "--force");
+            let broad: Vec<&String> = operands(args).filter(|p| is_broad_path(p)).collect();
+
+            if has_flag(args, "--no-preserve-root") {
+                flag(
+                    Risk::High,
+                    "rm with --no-preserve-root can delete the whole system".to_string(),
+                );
+            } else if recursive && !broad.is_empty() {
+                flag(Risk::High, format!("recursively deletes {}", join(&broad)));
+            } else if recursive && force {
+                flag(
+                    Risk::Medium,
+                    "force-deletes files recursively without asking".to_string(),
+                );
+            }
+        }
+        "dd" => {
+            if let Some(target) = args.iter().find_map(|arg| arg.strip_prefix("of=")) {
+                if target.starts_with("/dev/") && !matches!(target, "/dev/null" | "/dev/zero") {
+                    flag(
+                        Risk::High,
+                        format!("dd writes directly to the device {}", target),
+                    );
+                }
+            }
+        }
+        "wipefs" | "fdisk" | "sfdisk" | "parted" | "sgdisk" => {
+            flag(
+                Risk::High,
+                format!("{} can erase partition tables", program),
+            );
+        }
+        program if program == "mkfs" || program.starts_with("mkfs.") || program == "newfs" => {
+            flag(
+                Risk::High,
+                format!("{} formats a device, erasing its contents", program),
+            );
+        }
+        "shred" => {
+            flag(
+                Risk::Medium,
+                "shred irrecoverably overwrites files".to_string(),
+            );
+        }
+        "chmod" | "chown" | "chgrp" => {
+            let recursive = has_short_flag(args, 'R') || has_flag(args, "--recursive");
+            let broad: Vec<&String> = operands(args).filter(|p| is_broad_path(p)).collect();
+            if recursive && !broad.is_empty() {
+                flag(
+                    Risk::High,
+                    format!(
+                        "recursively changes ownership or permissions of {}",
+                        join(&broad)
+                    ),
+                );
+            } else if program == "chmod" && operands(args).any(|arg| arg == "777" || arg == "a+rwx")
+            {
+                flag(
+                    Risk::Medium,
+                    "makes files writable by every user".to_string(),
+                );
+            }
+        }
+        "git" => check_git(args, &mut flag),
+        "find" => {
+            if has_flag(args, "-delete") {
+                flag(Risk::Medium, "find -delete removes every match".to_string());
+            } else if let Some(pos) = args
+                .iter()
+                .position(|arg| arg == "-exec" || arg == "-execdir")
+            {
+                if args.get(pos + 1).is_some_and(|p| p == "rm") {
+                    flag(
+                        Risk::Medium,
+                        "find -exec rm removes every match".to_string(),
+                    );
+                }
+            }
+        }
+        "mv" if args.last().is_some_and(|dest| dest == "/dev/null") => {
+            flag(
+                Risk::Medium,
+                "moving files to /dev/null deletes them".to_string(),
+            );
+        }
+        "kubectl" if args.first().is_some_and(|sub| sub == "delete") => {
+            let risk =
+                if has_flag(args, "--all") || args.iter().any(|a| a == "namespace" || a == "ns") {
+                    Risk::High
+                } else {
+                    Risk::Medium
+                };
+            flag(risk, "kubectl delete removes cluster resources".to_string());
+        }
+        "terraform" | "tofu" if args.first().is_some_and(|sub| sub == "destroy") => {
+            flag(
+                Risk::High,
+                format!("{} destroy tears down all managed infrastructure", program),
+            );
+        }
+        "docker" | "podman" if args.iter().any(|arg| arg == "prune") => {
+            flag(
+                Risk::Medium,
+                format!("{} prune deletes unused data", program),
+            );
+        }
+        "shutdown" | "reboot" | "halt" | "poweroff" => {
+            flag(Risk::Medium, format!("{} stops the machine", program));
+        }
+        "iptables" | "ip6tables" if has_short_flag(args, 'F') || has_flag(args, "--flush") => {
+            flag(Risk::Medium, "flushes firewall rules".to_string());
+        }
+        _ => {}
+    }
+
+    // `sh -c "$(curl ...)"` and `bash <(curl ...)` run a download too
+    if INTERPRETERS.contains(&program)
+        && args.iter().any(|arg| {
+            ["$(curl", "$(wget", "<(curl", "<(wget", "`curl", "`wget"]
+                .iter()
+                .any(|pattern| arg.contains(pattern))
+        })
+    {
+        flag(
+            Risk::High,
+            format!(
+                "runs a downloaded script with {} without showing it first",
+                program
+            ),
+        );
+    }
+}
+
+fn check_git(args: &[String], flag: &mut impl FnMut(Risk, String)) {
+    let Some(subcommand) = args.iter().find(|arg| !arg.starts_with('-')) else {
+        return;
+    };
+    match subcommand.as_str() {
+        "push" => {
+            if has_flag(args, "--force")
+                || has_short_flag(args, 'f')
+                || args.iter().any(|a| a.starts_with('+'))
+            {
+                flag(
+                    Risk::Medium,
+                    "force push overwrites the remote branch's history".to_string(),
+                );
+            } else if args.iter().any(|a| a.starts_with("--force-with-lease")) {
+                flag(
+                    Risk::Low,
+                    "force push (with lease) rewrites the remote branch's history".to_string(),
+                );
+            } else if has_flag(args, "--delete") || args.iter().any(|a| a.starts_with(':')) {
+                flag(Risk::Medium, "deletes a remote branch".to_string());
+            }
+        }
+        "reset" if has_flag(args, "--hard") => {
+            flag(
+                Risk::Medium,
+                "git reset --hard discards uncommitted changes".to_string(),
+            );
+        }
+        "clean" if has_short_flag(args, 'f') || has_flag(args, "--force") => {
+            flag(
+                Risk::Medium,
+                "git clean deletes untracked files".to_string(),
+            );
+        }
+        "checkout" | "restore" if args.iter().any(|a| a == "." || a == "--") => {
+            flag(
+                Risk::Low,
+                "discards uncommitted changes to files".to_string(),
+            );
+        }
+        _ => {}
+    }
+}
+
+/// Patterns that are easier to spot in the raw text, like SQL inside a quoted argument
+fn check_text(command: &str, findings: &mut Vec<Finding>) {
+    let normalized = command
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_uppercase();
+
+    for statement in [
+        "DROP TABLE",
+        "DROP DATABASE",
+        "DROP SCHEMA",
+        "TRUNCATE TABLE",
+    ] {
+        if normalized.contains(statement) {
+            findings.push(Finding {
+                risk: Risk::High,
+                message: format!("{} permanently deletes data", statement),
+            });
+        }
+    }
+
+    if let Some(pos) = normalized.find("DELETE FROM") {
+        let statement = normalized[pos..].split(';').next().unwrap_or("");
+        if !statement.contains(" WHERE ") {
+            findings.push(Finding {
+                risk: Risk::Medium,
+                message: "DELETE FROM without WHERE deletes every row".to_string(),
+            });
+        }
+    }
+
+    if command.replace(' ', "").contains(":(){:|:&};:") {
+        findings.push(Finding {
+            risk: Risk::High,
+            message: "fork bomb".to_string(),
+        });
+    }
+}
+
+fn join(paths: &[&String]) -> String {
+    paths
+        .iter()
+        .map(|p| p.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn highest(command: &str) -> Option<Risk> {
+        analyze(command).first().map(|f| f.risk)
+    }
+
+    #[test]
+    fn test_parse_pipelines() {
+        let parsed = parse("cat 'a b.txt' | grep \"x\\\"y\" > out.txt && echo done # comment");
+
+        assert_eq!(parsed.pipelines.len(), 2);
+        assert_eq!(parsed.pipelines[0][0].words, vec!["cat", "a b.txt"]);
+        assert_eq!(parsed.pipelines[0][1].words, vec!["grep", "x\"y"]);
+        assert_eq!(parsed.pipelines[0][1].redirects, vec!["out.txt"]);
+        assert_eq!(parsed.pipelines[1][0].words, vec!["echo", "done"]);
+    }
+
+    #[test]
+    fn test_parse_substitutions_and_heredocs() {
+        let parsed = parse("echo $(date +%s) 2>&1\ncat <<'EOF'\nrm -rf /\nEOF\nls");
+
+        assert_eq!(parsed.substitutions, vec!["date +%s"]);
+        assert_eq!(parsed.pipelines.len(), 3);
+        assert_eq!(parsed.pipelines[2][0].words, vec!["ls"]);
+    }
+
+    #[test]
+    fn test_rm() {
+        assert_eq!(highest("rm -rf /"), Some(Risk::High));
+        assert_eq!(highest("sudo rm -rf ~/"), Some(Risk::High));
+        assert_eq!(highest("rm -r -f /usr/*"), Some(Risk::High));
+        assert_eq!(highest("rm --no-preserve-root -rf /"), Some(Risk::High));
+        assert_eq!(highest("rm -rf ./build"), Some(Risk::Medium));
+        assert_eq!(highest("rm notes.txt"), None);
+        assert_eq!(highest("echo 'rm -rf /'"), None);
+    }
+
+    #[test]
+    fn test_devices_and_filesystems() {
+        assert_eq!(
+            highest("dd if=ubuntu.iso of=/dev/sdb bs=4M"),
+            Some(Risk::High)
+        );
+        assert_eq!(highest("dd if=/dev/zero of=test.img bs=1M count=10"), None);
+        assert_eq!(highest("mkfs.ext4 /dev/sdb1"), Some(Risk::High));
+        assert_eq!(highest("cat image.raw > /dev/nvme0n1"), Some(Risk::High));
+        assert_eq!(highest("ls > /dev/null 2>&1"), None);
+    }
+
+    #[test]
+    fn test_permissions() {
+        assert_eq!(highest("chmod -R 777 /"), Some(Risk::High));
+        assert_eq!(highest("sudo chown -R me:me /usr"), Some(Risk::High));
+        assert_eq!(highest("chmod 777 script.sh"), Some(Risk::Medium));
+        assert_eq!(highest("chmod +x script.sh"), None);
+    }
+
+    #[test]
+    fn test_remote_scripts() {
+        assert_eq!(
+            highest("curl -fsSL https://example.com/install.sh | sh"),
+            Some(Risk::High)
+        );
+        assert_eq!(
+            highest("wget -qO- https://example.com | sudo bash"),
+            Some(Risk::High)
+        );
+        assert_eq!(
+            highest("sh -c \"$(curl -fsSL https://example.com)\""),
+            Some(Risk::High)
+        );
+        assert_eq!(
+            highest("bash <(curl -s https://example.com)"),
+            Some(Risk::High)
+        );
+        assert_eq!(highest("curl -s https://example.com | jq ."), None);
+    }
+
+    #[test]
+    fn test_git() {
+        assert_eq!(highest("git push --force origin main"), Some(Risk::Medium));
+        assert_eq!(highest("git push -f"), Some(Risk::Medium));
+        assert_eq!(highest("git push origin +main"), Some(Risk::Medium));
+        assert_eq!(highest("git push --force-with-lease"), Some(Risk::Low));
+        assert_eq!(highest("git reset --hard HEAD~1"), Some(Risk::Medium));
+        assert_eq!(highest("git push origin main"), None);
+    }
+
+    #[test]
+    fn test_sql() {
+        assert_eq!(highest("psql -c 'drop   table users'"), Some(Risk::High));
+        assert_eq!(
+            highest("mysql -e \"DELETE FROM users\""),
+            Some(Risk::Medium)
+        );
+        assert_eq!(highest("mysql -e \"DELETE FROM users WHERE id = 3\""), None);
+    }
+
+    #[test]
+    fn test_substitution_is_analyzed() {
+        assert_eq!(highest("echo $(rm -rf /)"), Some(Risk::High));
+    }
+
+    #[test]
+    fn test_highest_risk_first() {
+        let findings = analyze("git reset --hard && rm -rf /");
+        assert_eq!(findings.len(), 2);
+        assert_eq!(findings[0].risk, Risk::High);
+        assert_eq!(findings[1].risk, Risk::Medium);
+        assert_eq!(findings[0].to_string(), "recursively deletes / (high risk)");
+    }
+}
@@ -0,0 +1,943 @@
+//! Answering requests the way the `halp` binary does: asking the provider (or
+//! the cache), checking the commands against the policy and the safety
+//! warnings, and printing, running or refining them
+
+use crate::cache::{Cache, Cached};
+use crate::chat::{self, parse_input, Input, LineReader};
+use crate::config::Config;
+use crate::error::HalpError;
+use crate::history::{Entry, History};
+use crate::json::{CommandOutput, ErrorOutput, JsonOutput, ResponseMeta};
+use crate::offline::{self, Source};
+use crate::output::{
+    parse_breakdown, parse_response, pick_candidate, Breakdown, Candidate, NullWriter, Spinner,
+    StderrStreamer,
+};
+use crate::policy::{Action, Policy};
+use crate::prompt::build_fix_prompt;
+use crate::providers::{Conversation, LlmProvider, ProviderBuilder};
+use crate::run;
+use crate::safety::{self, Risk};
+use std::io::{self, IsTerminal, Write};
+use std::time::{Duration, Instant};
+
+/// Exit status when the command is high risk and `fail_on_high_risk` is set
+pub const HIGH_RISK_EXIT: u8 = 11;
+/// Exit status when the policy denies every command in the response
+pub const POLICY_DENIED_EXIT: u8 = 12;
+
+const SUCCESS: u8 = 0;
+const FAILURE: u8 = 1;
+
+/// How to answer a single request
+#[derive(Debug, Clone, Default)]
+pub struct Mode {
+    /// Print a single JSON object on stdout, an error included, and nothing
+    /// on stderr
+    pub json: bool,
+    /// The request is for a breakdown of a command (`halp explain`)
+    pub breakdown: bool,
+    /// Print the explanation instead of the command
+    pub explanation_only: bool,
+    /// Confirm and run the command, with up to this many runs in all
+    pub run: Option<u32>,
+    /// Ask the provider even if there's a cached response
+    pub no_cache: bool,
+    /// Suggest a command from the history or the recipes if the provider
+    /// can't be reached
+    pub offline: bool,
+}
+
+/// A response, and where it came from
+struct Reply {
+    response: String,
+    provider: String,
+    model: String,
+    cached: bool,
+    latency_ms: u64,
+    /// Notes for the JSON output, which would otherwise go to stderr
+    warnings: Vec<String>,
+}
+
+/// A provider and everything needed to answer requests with it, record the
+/// answers and run them
+pub struct Session {
+    provider: Box<dyn LlmProvider>,
+    /// Label, provider and model of the primary and each fallback
+    chain: Vec<(String, String, String)>,
+    system_prompt: String,
+    policy: Policy,
+    /// The user's query, for the history
+    query: String,
+    history: Option<History>,
+    cache: Option<Cache>,
+    cache_ttl: Duration,
+    quiet: bool,
+    fail_on_high_risk: bool,
+    /// Most bytes of a failed command's stderr to send back
+    max_stderr_bytes: usize,
+}
+
+impl Session {
+    /// Create the configured provider, with its fallbacks, and open the
+    /// history and cache unless the configuration turns them off
+    pub fn new(
+        config: Config,
+        policy: Policy,
+        system_prompt: String,
+        race: bool,
+    ) -> Result<Self, HalpError> {
+        let race = race || config.race;
+        let provider = ProviderBuilder::from_config(config.clone())
+            .race(race)
+            .build()?;
+        Ok(Self::with_provider(
+            provider,
+            &config,
+            policy,
+            system_prompt,
+        ))
+    }
+
+    fn with_provider(
+        provider: Box<dyn LlmProvider>,
+        config: &Config,
+        policy: Policy,
+        system_prompt: String,
+    ) -> Self {
+        Session {
+            provider,
+            chain: std::iter::once(config)
+                .chain(config.fallbacks.iter())
+                .map(|c| (c.label(), c.provider.to_string(), c.model.clone()))
+                .collect(),
+            system_prompt,
+            policy,
+            query: String::new(),
+            history: if config.history {
+                History::open()
+            } else {
+                None
+            },
+            cache: if config.cache_ttl.is_zero() {
+                None
+            } else {
+                Cache::open()
+            },
+            cache_ttl: config.cache_ttl,
+            quiet: false,
+            fail_on_high_risk: config.fail_on_high_risk,
+            max_stderr_bytes: config.max_stdin_bytes,
+        }
+    }
+
+    /// The user's query as it should appear in the history
+    pub fn query(mut self, query: impl Into<String>) -> Self {
+        self.query = query.into();
+        self
+    }
+
+    /// Don't stream explanations to stderr
+    pub fn quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// Don't record the commands in the history
+    pub fn without_history(mut self) -> Self {
+        self.history = None;
+        self
+    }
+
+    /// Provider and model that answered the last request
+    fn answered(&self) -> (String, String) {
+        let (_, provider, model) = self
+            .provider
+            .answered_by()
+            .and_then(|label| self.chain.iter().find(|(l, _, _)| *l == label))
+            .unwrap_or(&self.chain[0])
+            .clone();
+        (provider, model)
+    }
+
+    /// Add the command to the history, unless it's turned off, warning on
+    /// stderr if that fails
+    fn remember(&self, query: &str, command: &str, explanation: Option<&str>) {
+        if let Err(e) = self.record(query, command, explanation) {
+            eprintln!("Warning: {}", e);
+        }
+    }
+
+    /// Add the command to the history, unless it's turned off
+    fn record(&self, query: &str, command: &str, explanation: Option<&str>) -> Result<(), String> {
+        let Some(history) = &self.history else {
+            return Ok(());
+        };
+        let (provider, model) = self.answered();
+        let entry = Entry::new(query, command, explanation, &provider, &model);
+        history
+            .record(&entry)
+            .map_err(|e| format!("Failed to save history: {}", e))
+    }
+
+    /// The reply to a new conversation: from the cache if there's a fresh
+    /// one, otherwise streamed from the provider and cached if it has
+    /// something to show. A breakdown is printed formatted once it's
+    /// complete, so it isn't echoed raw as well.
+    async fn ask(&self, conversation: &Conversation, mode: &Mode) -> Result<Reply, HalpError> {
+        let quiet = self.quiet || mode.json;
+        let started = Instant::now();
+        let (_, primary_provider, primary_model) = &self.chain[0];
+        let key = Cache::key(
+            primary_provider,
+            primary_model,
+            &self.system_prompt,
+            conversation,
+        );
+        let cached = match &self.cache {
+            Some(cache) if !mode.no_cache => cache.get(&key, self.cache_ttl),
+            _ => None,
+        };
+        if let Some(cached) = cached {
+            if !quiet {
+                if !mode.breakdown {
+                    echo_cached(&cached.response);
+                }
+                eprintln!("Cached response (--no-cache asks again)");
+            }
+            return Ok(Reply {
+                response: cached.response,
+                provider: cached.provider,
+                model: cached.model,
+                cached: true,
+                latency_ms: started.elapsed().as_millis() as u64,
+                warnings: Vec::new(),
+            });
+        }
+
+        let response = stream_reply(
+            self.provider.as_ref(),
+            conversation,
+            &self.system_prompt,
+            quiet,
+            !mode.breakdown,
+        )
+        .await?;
+        let latency_ms = started.elapsed().as_millis() as u64;
+        let (provider, model) = self.answered();
+
+        let mut warnings = Vec::new();
+        if let Some(cache) = &self.cache {
+            let usable = if mode.breakdown {
+                parse_breakdown(&response).is_some()
+            } else {
+                !parse_response(&response).candidates.is_empty()
+            };
+            if usable {
+                let entry = Cached::new(&provider, &model, &response);
+                if let Err(e) = cache.put(&key, &entry) {
+                    let warning = format!("Failed to save the response to the cache: {}", e);
+                    if mode.json {
+                        warnings.push(warning);
+                    } else {
+                        eprintln!("Warning: {}", warning);
+                    }
+                }
+            }
+        }
+        Ok(Reply {
+            response,
+            provider,
+            model,
+            cached: false,
+            latency_ms,
+            warnings,
+        })
+    }
+
+    /// Answer a request and print, or run, the result. `warnings` are notes
+    /// for the JSON output. Returns the exit status.
+    pub async fn answer(&self, prompt: String, mode: &Mode, mut warnings: Vec<String>) -> u8 {
+        let mut conversation = Conversation::new(prompt);
+        let mut reply = match self.ask(&conversation, mode).await {
+            Ok(reply) => reply,
+            Err(e) if mode.json => {
+                println!("{}", ErrorOutput::new(&e, e.exit_code()).to_json());
+                return e.exit_code();
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                // Without a network, something close from earlier beats nothing. The
+                // exit status still says the provider didn't answer.
+                let offline = matches!(e, HalpError::Network(_) | HalpError::Timeout(_));
+                if offline && mode.offline && mode.run.is_none() && !mode.explanation_only {
+                    suggest_offline(&self.query, &self.policy, self.quiet);
+                }
+                return e.exit_code();
+            }
+        };
+
+        warnings.append(&mut reply.warnings);
+        if let Some(label) = self.provider.answered_by() {
+            if mode.json {
+                warnings.push(format!("Answered by fallback {}", label));
+            } else if !self.quiet {
+                eprintln!("Answered by {}", label);
+            }
+        }
+        let meta = |warnings| ResponseMeta {
+            provider: reply.provider.clone(),
+            model: reply.model.clone(),
+            latency_ms: reply.latency_ms,
+            usage: self.provider.usage(),
+            cached: reply.cached,
+            warnings,
+        };
+        let response = &reply.response;
+
+        if mode.breakdown {
+            let breakdown = parse_breakdown(response);
+            if mode.json {
+                if breakdown.is_none() {
+                    warnings.push("Could not parse breakdown from response".to_string());
+                }
+                let breakdown = breakdown.unwrap_or_else(|| Breakdown {
+                    summary: Some(response.trim().to_string()),
+                    stages: Vec::new(),
+                });
+                let output = JsonOutput {
+                    result: breakdown,
+                    meta: meta(warnings),
+                };
+                println!("{}", output.to_json());
+                return SUCCESS;
+            }
+            match breakdown {
+                Some(breakdown) => println!("{}", breakdown),
+                None => println!("{}", response.trim()),
+            }
+            return SUCCESS;
+        }
+
+        // Parse the response, dropping any commands the policy denies
+        let mut parsed = parse_response(response);
+        let denied = self.policy.remove_denied(&mut parsed);
+        let all_denied = parsed.candidates.is_empty() && !denied.is_empty();
+
+        if mode.json {
+            let found = parsed.command().is_some();
+            warnings.extend(denied);
+            if !found && !all_denied {
+                warnings.push("Could not extract command from response".to_string());
+            }
+            if let Some(candidate) = parsed.candidates.first() {
+                let recorded = self.record(
+                    &self.query,
+                    &candidate.command,
+                    candidate.explanation.as_deref(),
+                );
+                warnings.extend(recorded.err());
+            }
+            let result = CommandOutput::new(&parsed, &self.policy, &mut warnings);
+            let high_risk = result.high_risk;
+            let output = JsonOutput {
+                result,
+                meta: meta(warnings),
+            };
+            println!("{}", output.to_json());
+            return if all_denied {
+                POLICY_DENIED_EXIT
+            } else if !found {
+                FAILURE
+            } else if high_risk && self.fail_on_high_risk {
+                HIGH_RISK_EXIT
+            } else {
+                SUCCESS
+            };
+        }
+
+        for message in &denied {
+            eprintln!("{}", message);
+        }
+        if all_denied {
+            return POLICY_DENIED_EXIT;
+        }
+
+        // With alternatives, let the user choose when there's a terminal to ask on
+        let chosen = if parsed.candidates.len() > 1 && io::stderr().is_terminal() {
+            match pick_candidate(&parsed.candidates) {
+                Ok(Some(index)) => parsed.candidates.get(index),
+                Ok(None) => {
+                    eprintln!("No command selected");
+                    return FAILURE;
+                }
+                Err(e) => {
+                    eprintln!("Error: Failed to show picker: {}", e);
+                    return FAILURE;
+                }
+            }
+        } else {
+            parsed.candidates.first()
+        };
+
+        // Flag destructive commands before they reach the user's prompt, even with --quiet
+        let high_risk = chosen.is_some_and(|c| warn_about(&self.policy, &c.command));
+
+        if let Some(attempts) = mode.run {
+            let Some(candidate) = chosen else {
+                eprintln!("Could not extract command from response");
+                return FAILURE;
+            };
+            if high_risk && self.fail_on_high_risk {
+                eprintln!("Not running a high-risk command (fail_on_high_risk is set)");
+                return HIGH_RISK_EXIT;
+            }
+            conversation.push_assistant(response.clone());
+            return self.run(candidate.clone(), conversation, attempts).await;
+        }
+
+        if mode.explanation_only {
+            // Explanation-only mode: output explanation to stdout
+            if let Some(explanation) = chosen.and_then(|c| c.explanation.as_ref()) {
+                println!("{}", explanation);
+            }
+        } else {
+            // Normal mode: output command to stdout
+            if let Some(candidate) = chosen {
+                self.remember(
+                    &self.query,
+                    &candidate.command,
+                    candidate.explanation.as_deref(),
+                );
+                println!("{}", candidate.command);
+            } else {
+                eprintln!("Could not extract command from response");
+                return FAILURE;
+            }
+        }
+
+        if high_risk && self.fail_on_high_risk {
+            return HIGH_RISK_EXIT;
+        }
+        SUCCESS
+    }
+
+    /// Confirm and run the command. If it fails and there are attempts left,
+    /// send it back with its exit status and stderr as a follow-up to the
+    /// conversation, and confirm and run the corrected command.
+    async fn run(
+        &self,
+        mut candidate: Candidate,
+        mut conversation: Conversation,
+        attempts: u32,
+    ) -> u8 {
+        for attempt in 1..=attempts {
+            let command_to_run = match run::confirm(&candidate.command) {
+                Ok(Some(command)) => command,
+                Ok(None) => {
+                    eprintln!("Not running");
+                    return FAILURE;
+                }
+                Err(e) => {
+                    eprintln!(
+                        "Error: Failed to ask for confirmation on the terminal: {}",
+                        e
+                    );
+                    return FAILURE;
+                }
+            };
+            self.remember(
+                &self.query,
+                &command_to_run,
+                candidate.explanation.as_deref(),
+            );
+
+            // Only capture stderr when there's another attempt to send it to
+            let result = if attempt < attempts {
+                run::run_capturing_stderr(&command_to_run, self.max_stderr_bytes)
+            } else {
+                run::run_in_shell(&command_to_run).map(|status| (status, None))
+            };
+            let (status, stderr) = match result {
+                Ok(result) => result,
+                Err(e) => {
+                    eprintln!("Error: Failed to run the command: {}", e);
+                    return FAILURE;
+                }
+            };
+            if status.success() || attempt == attempts {
+                if !status.success() && attempts > 1 {
+                    eprintln!("Still failing after {} attempts", attempts);
+                }
+                return run::exit_code(status);
+            }
+
+            eprintln!(
+                "Command failed with exit status {}, asking for a fix (attempt {} of {})",
+                run::exit_code(status),
+                attempt + 1,
+                attempts
+            );
+            conversation.push_user(build_fix_prompt(
+                &command_to_run,
+                status.code(),
+                stderr.as_ref(),
+            ));
+            let response = match stream_reply(
+                self.provider.as_ref(),
+                &conversation,
+                &self.system_prompt,
+                self.quiet,
+                true,
+            )
+            .await
+            {
+                Ok(response) => response,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    return e.exit_code();
+                }
+            };
+            conversation.push_assistant(response.clone());
+
+            let mut parsed = parse_response(&response);
+            let denied = self.policy.remove_denied(&mut parsed);
+            for message in &denied {
+                eprintln!("{}", message);
+            }
+            let Some(corrected) = parsed.candidates.into_iter().next() else {
+                if !denied.is_empty() {
+                    return POLICY_DENIED_EXIT;
+                }
+                eprintln!("Could not extract command from response");
+                return FAILURE;
+            };
+            if warn_about(&self.policy, &corrected.command) && self.fail_on_high_risk {
+                eprintln!("Not running a high-risk command (fail_on_high_risk is set)");
+                return HIGH_RISK_EXIT;
+            }
+            candidate = corrected;
+        }
+        // `attempts` is at least 1, and the last attempt always returns
+        FAILURE
+    }
+
+    /// An interactive session: each request or follow-up shows a command,
+    /// which can be refined further, then accepted, copied or run. Returns
+    /// the exit status.
+    pub async fn chat(&self, first: Option<String>) -> u8 {
+        let mut reader = match LineReader::open() {
+            Ok(reader) => reader,
+            Err(e) => {
+                eprintln!("Error: Failed to open the terminal: {}", e);
+                return FAILURE;
+            }
+        };
+        eprintln!(
+            "Describe the command you need, then refine it with follow-ups. /help for commands."
+        );
+
+        let mut conversation = Conversation::default();
+        // What the user asked for so far, for the history
+        let mut asked: Vec<String> = Vec::new();
+        // The latest command, and whether it's high risk
+        let mut current: Option<(Candidate, bool)> = None;
+        let mut pending = first;
+
+        loop {
+            let input = match pending.take() {
+                Some(prompt) => {
+                    asked.push(self.query.clone());
+                    Input::Query(prompt)
+                }
+                None => match reader.read_line("halp> ") {
+                    Ok(Some(line)) => {
+                        let input = parse_input(&line);
+                        if let Input::Query(query) = &input {
+                            asked.push(query.clone());
+                        }
+                        input
+                    }
+                    Ok(None) => Input::Quit,
+                    Err(e) => {
+                        eprintln!("Error: {}", e);
+                        return FAILURE;
+                    }
+                },
+            };
+
+            match input {
+                Input::Query(query) => {
+                    conversation.push_user(query);
+                    let response = match stream_reply(
+                        self.provider.as_ref(),
+                        &conversation,
+                        &self.system_prompt,
+                        self.quiet,
+                        true,
+                    )
+                    .await
+                    {
+                        Ok(response) => response,
+                        Err(e) => {
+                            // Drop the request so it can be retried or rephrased
+                            conversation.turns.pop();
+                            asked.pop();
+                            eprintln!("Error: {}", e);
+                            continue;
+                        }
+                    };
+                    conversation.push_assistant(response.clone());
+
+                    let mut parsed = parse_response(&response);
+                    for message in self.policy.remove_denied(&mut parsed) {
+                        eprintln!("{}", message);
+                    }
+                    match parsed.candidates.into_iter().next() {
+                        Some(candidate) => {
+                            eprintln!("{}", chat::highlight(&candidate.command));
+                            let high_risk = warn_about(&self.policy, &candidate.command);
+                            current = Some((candidate, high_risk));
+                        }
+                        None => eprintln!("Could not extract command from response"),
+                    }
+                }
+                Input::Accept => match &current {
+                    Some((candidate, high_risk)) => {
+                        self.remember(
+                            &asked.join("; "),
+                            &candidate.command,
+                            candidate.explanation.as_deref(),
+                        );
+                        println!("{}", candidate.command);
+                        if *high_risk && self.fail_on_high_risk {
+                            return HIGH_RISK_EXIT;
+                        }
+                        return SUCCESS;
+                    }
+                    None => eprintln!("No command yet"),
+                },
+                Input::Copy => match &current {
+                    Some((candidate, _)) => match chat::copy_to_clipboard(&candidate.command) {
+                        Ok(()) => {
+                            self.remember(
+                                &asked.join("; "),
+                                &candidate.command,
+                                candidate.explanation.as_deref(),
+                            );
+                            eprintln!("Copied to the clipboard")
+                        }
+                        Err(e) => eprintln!("Error: Failed to copy: {}", e),
+                    },
+                    None => eprintln!("No command yet"),
+                },
+                Input::Run => match &current {
+                    Some((_, true)) if self.fail_on_high_risk => {
+                        eprintln!("Not running a high-risk command (fail_on_high_risk is set)");
+                    }
+                    Some((candidate, high_risk)) => {
+                        self.run_in_chat(candidate, *high_risk, &asked.join("; "))
+                    }
+                    None => eprintln!("No command yet"),
+                },
+                Input::New => {
+                    conversation = Conversation::default();
+                    asked.clear();
+                    current = None;
+                    eprintln!("Starting over");
+                }
+                Input::Help => eprintln!("{}", chat::HELP),
+                Input::Unknown(command) => {
+                    eprintln!("Unknown command {} (/help lists them)", command)
+                }
+                Input::Empty => {}
+                Input::Quit => return FAILURE,
+            }
+        }
+    }
+
+    /// Run a command from a chat session, which goes on afterwards. Asking
+    /// for /run is confirmation enough, unless the command is high risk or
+    /// the policy wants it confirmed.
+    fn run_in_chat(&self, candidate: &Candidate, high_risk: bool, query: &str) {
+        let command = &candidate.command;
+        let command = if high_risk || self.policy.confirmation(command).is_some() {
+            match run::confirm(command) {
+                Ok(Some(command)) => command,
+                Ok(None) => return,
+                Err(e) => {
+                    eprintln!(
+                        "Error: Failed to ask for confirmation on the terminal: {}",
+                        e
+                    );
+                    return;
+                }
+            }
+        } else {
+            command.to_string()
+        };
+        self.remember(query, &command, candidate.explanation.as_deref());
+        match run::run_in_shell(&command) {
+            Ok(status) if !status.success() => {
+                eprintln!("Exited with status {}", run::exit_code(status))
+            }
+            Ok(_) => {}
+            Err(e) => eprintln!("Error: Failed to run the command: {}", e),
+        }
+    }
+}
+
+/// Print the closest command from the history or the bundled recipes when
+/// the provider can't be reached, marked as an offline suggestion
+fn suggest_offline(query: &str, policy: &Policy, quiet: bool) {
+    let entries = History::open()
+        .and_then(|history| history.entries().ok())
+        .unwrap_or_default();
+    let Some(suggestion) = offline::suggest(query, &entries).into_iter().find(|s| {
+        !policy
+            .check(&s.command)
+            .is_some_and(|rule| rule.action == Action::Deny)
+    }) else {
+        return;
+    };
+
+    match &suggestion.source {
+        Source::History(entry) => eprintln!(
+            "Offline suggestion from your history (for \"{}\", {}):",
+            entry.query,
+            entry.age()
+        ),
+        Source::Recipe => eprintln!("Offline suggestion from the built-in recipes:"),
+    }
+    if !quiet {
+        if let Some(explanation) = &suggestion.explanation {
+            eprintln!("{}", explanation);
+        }
+    }
+    warn_about(policy, &suggestion.command);
+    println!("{}", suggestion.command);
+}
+
+/// Show a cached response the way `stream_reply` shows one as it arrives
+fn echo_cached(response: &str) {
+    let mut writer = StderrStreamer::new(None);
+    let _ = writer.write_all(response.as_bytes());
+    writer.finish();
+}
+
+/// Stream a reply to stderr, or nowhere when quiet, with a spinner until it
+/// starts. Without `echo` only the spinner is shown.
+async fn stream_reply(
+    provider: &dyn LlmProvider,
+    conversation: &Conversation,
+    system_prompt: &str,
+    quiet: bool,
+    echo: bool,
+) -> Result<String, HalpError> {
+    if quiet {
+        return provider
+            .stream_conversation(conversation, system_prompt, &mut NullWriter)
+            .await;
+    }
+    let spinner = Spinner::start();
+    let mut writer = if echo {
+        StderrStreamer::new(Some(spinner))
+    } else {
+        StderrStreamer::spinner_only(spinner)
+    };
+    let result = provider
+        .stream_conversation(conversation, system_prompt, &mut writer)
+        .await;
+    writer.finish();
+    result
+}
+
+/// Print safety warnings and policy notes about the command to stderr,
+/// returning whether it's high risk
+fn warn_about(policy: &Policy, command: &str) -> bool {
+    let findings = safety::analyze(command);
+    for finding in &findings {
+        eprintln!("Warning: {}", finding);
+    }
+    if let Some(message) = policy.confirmation(command) {
+        eprintln!("{}", message);
+    }
+    findings.iter().any(|f| f.risk == Risk::High)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Provider;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Always gives the same reply, counting the requests
+    struct StubProvider {
+        response: &'static str,
+        requests: Arc<AtomicUsize>,
+    }
+
+    #[async_trait::async_trait]
+    impl LlmProvider for StubProvider {
+        async fn stream_conversation(
+            &self,
+            _conversation: &Conversation,
+            _system: &str,
+            _output: &mut (dyn Write + Send),
+        ) -> Result<String, HalpError> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            Ok(self.response.to_string())
+        }
+    }
+
+    /// Can never be reached
+    struct UnreachableProvider;
+
+    #[async_trait::async_trait]
+    impl LlmProvider for UnreachableProvider {
+        async fn stream_conversation(
+            &self,
+            _conversation: &Conversation,
+            _system: &str,
+            _output: &mut (dyn Write + Send),
+        ) -> Result<String, HalpError> {
+            Err(HalpError::Network("connection refused".to_string()))
+        }
+    }
+
+    /// A session with a stub provider and a cache in a fresh directory,
+    /// and the counter of requests the provider got
+    fn session(response: &'static str, name: &str) -> (Session, Arc<AtomicUsize>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let provider = StubProvider {
+            response,
+            requests: requests.clone(),
+        };
+        let mut config = Config::new(Provider::Ollama);
+        config.model = "llama3.2".to_string();
+        let mut session = Session::with_provider(
+            Box::new(provider),
+            &config,
+            Policy::default(),
+            "system".to_string(),
+        )
+        .quiet(true)
+        .without_history();
+        let dir =
+            std::env::temp_dir().join(format!("halp-session-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        session.cache = Some(Cache::at(dir));
+        session.cache_ttl = Duration::from_secs(60);
+        (session, requests)
+    }
+
+    #[tokio::test]
+    async fn test_ask_reuses_cached_response() {
+        let (session, requests) = session("COMMAND: ls -la", "reuse");
+        let conversation = Conversation::new("list files");
+        let mode = Mode::default();
+
+        let first = session.ask(&conversation, &mode).await.unwrap();
+        let second = session.ask(&conversation, &mode).await.unwrap();
+
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        assert!(!first.cached);
+        assert!(second.cached);
+        assert_eq!(second.response, "COMMAND: ls -la");
+        assert_eq!(
+            (second.provider.as_str(), second.model.as_str()),
+            ("ollama", "llama3.2")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ask_without_cache_asks_again() {
+        let (session, requests) = session("COMMAND: ls -la", "no-cache");
+        let conversation = Conversation::new("list files");
+        let no_cache = Mode {
+            no_cache: true,
+            ..Mode::default()
+        };
+
+        session.ask(&conversation, &Mode::default()).await.unwrap();
+        let reply = session.ask(&conversation, &no_cache).await.unwrap();
+
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert!(!reply.cached);
+    }
+
+    #[tokio::test]
+    async fn test_ask_skips_caching_unusable_response() {
+        let (session, requests) = session("", "unusable");
+        let conversation = Conversation::new("list files");
+        let breakdown = Mode {
+            breakdown: true,
+            ..Mode::default()
+        };
+
+        session.ask(&conversation, &Mode::default()).await.unwrap();
+        session.ask(&conversation, &breakdown).await.unwrap();
+        session.ask(&conversation, &Mode::default()).await.unwrap();
+
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_answer_exit_status() {
+        let (mut session, _) = session("COMMAND: rm -rf /\nEXPLANATION: Delete everything", "exit");
+        let json = Mode {
+            json: true,
+            ..Mode::default()
+        };
+
+        assert_eq!(
+            session.answer("q".to_string(), &json, Vec::new()).await,
+            SUCCESS
+        );
+        session.fail_on_high_risk = true;
+        assert_eq!(
+            session.answer("q".to_string(), &json, Vec::new()).await,
+            HIGH_RISK_EXIT
+        );
+        session.policy = Policy::from_toml("[[rule]]\naction = \"deny\"\nglob = \"rm *\"").unwrap();
+        assert_eq!(
+            session.answer("q".to_string(), &json, Vec::new()).await,
+            POLICY_DENIED_EXIT
+        );
+    }
+
+    #[tokio::test]
+    async fn test_answer_checks_alternatives() {
+        let (mut session, _) = session(
+            "COMMAND: ls build\nEXPLANATION: List it\n\nCOMMAND: rm -rf /\nEXPLANATION: Delete everything",
+            "alternatives",
+        );
+        session.fail_on_high_risk = true;
+        let json = Mode {
+            json: true,
+            ..Mode::default()
+        };
+
+        assert_eq!(
+            session.answer("q".to_string(), &json, Vec::new()).await,
+            HIGH_RISK_EXIT
+        );
+    }
+
+    #[tokio::test]
+    async fn test_answer_reports_provider_error() {
+        let (mut session, _) = session("", "error");
+        session.provider = Box::new(UnreachableProvider);
+        let json = Mode {
+            json: true,
+            offline: true,
+            ..Mode::default()
+        };
+
+        assert_eq!(
+            session.answer("q".to_string(), &json, Vec::new()).await,
+            HalpError::Network(String::new()).exit_code()
+        );
+    }
+}